@@ -0,0 +1,85 @@
+// The board information is read from the device tree blob(DTB), whose physical
+// address is passed by the SBI in a1. The DTB is parsed once during booting,
+// before the frame allocator might recycle the memory it lives in.
+//
+// Execute following commands to check the DTB for QEMU:
+//   qemu-system-riscv64 -machine virt,dumpdtb=dump.dtb
+//   dtc -o dump.dts dump.dtb
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use fdt::Fdt;
+use lazy_static::*;
+
+pub struct BoardInfo {
+    // The end of the physical memory which the kernel is loaded into.
+    pub memory_end: usize,
+    // The frequency of the `time` CSR, i.e. `timebase-frequency` in cpus.
+    pub clock_freq: usize,
+    // The [base, end) of the registers of each virtio-mmio device.
+    pub virtio_mmio: Vec<(usize, usize)>,
+}
+
+lazy_static! {
+    pub static ref BOARD_INFO: UPSafeCell<BoardInfo> = unsafe {
+        UPSafeCell::new(BoardInfo {
+            memory_end: 0,
+            clock_freq: 0,
+            virtio_mmio: Vec::new(),
+        })
+    };
+}
+
+// It depends on the heap allocator.
+pub fn init(dtb_pa: usize) {
+    extern "C" {
+        fn skernel();
+    }
+    let fdt = match unsafe { Fdt::from_ptr(dtb_pa as *const u8) } {
+        Ok(fdt) => fdt,
+        Err(err) => panic!("Invalid device tree at {:#x}: {:?}", dtb_pa, err),
+    };
+    let mut info = BOARD_INFO.exclusive_access();
+    // There might be multiple memory regions, only the one holding the kernel
+    // is used.
+    info.memory_end = fdt
+        .memory()
+        .regions()
+        .map(|region| {
+            let start = region.starting_address as usize;
+            (start, start + region.size.unwrap_or(0))
+        })
+        .find(|&(start, end)| (start..end).contains(&(skernel as usize)))
+        .expect("Cannot find the memory region of the kernel")
+        .1;
+    info.clock_freq = fdt
+        .cpus()
+        .next()
+        .expect("Cannot find any cpu")
+        .timebase_frequency();
+    info.virtio_mmio = fdt
+        .all_nodes()
+        .filter(|node| {
+            node.compatible()
+                .map_or(false, |c| c.all().any(|s| s == "virtio,mmio"))
+        })
+        .filter_map(|node| node.reg()?.next())
+        .map(|reg| {
+            let base = reg.starting_address as usize;
+            (base, base + reg.size.unwrap_or(0))
+        })
+        .collect();
+    println!(
+        "[kernel] Memory ends at {:#x}, clock frequency {} Hz, {} virtio-mmio devices",
+        info.memory_end,
+        info.clock_freq,
+        info.virtio_mmio.len()
+    );
+}
+
+pub fn memory_end() -> usize {
+    BOARD_INFO.exclusive_access().memory_end
+}
+
+pub fn clock_freq() -> usize {
+    BOARD_INFO.exclusive_access().clock_freq
+}
@@ -1,27 +1,50 @@
 pub const USER_STACK_SIZE: usize = 4096 * 2;
 pub const KERNEL_STACK_SIZE: usize = 4096 * 2;
-pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
+// The initial size of the kernel heap, it grows by taking frames later.
+pub const KERNEL_HEAP_SIZE: usize = 0x10_0000;
 
-// Execute following commands to get the CLOCK_FREQ for QEMU:
-//   qemu-system-riscv64 -machine virt,dumpdtb=dump.dtb
-//   dtc -o dump.dts dump.dtb
-// Then check `timebase-frequency` in cpus.
-pub const CLOCK_FREQ: usize = 10_000_000;
 pub const MICRO_PER_SEC: usize = 1_000_000;
+pub const MILLI_PER_SEC: usize = 1_000;
 
-pub const MEMORY_END: usize = 0x80800000;
+// The syscalls are counted for each task if the syscall id is less than it.
+pub const MAX_SYSCALL_NUM: usize = 500;
+
+// Stride scheduling(sched-stride), the priority of a task must be at least 2
+// and at most BIG_STRIDE, otherwise its stride would be 0.
+pub const DEFAULT_PRIORITY: usize = 16;
+pub const BIG_STRIDE: usize = 1 << 32;
 
 pub const PAGE_SIZE_BITS: usize = 12;
 pub const PAGE_SIZE: usize = 1usize << PAGE_SIZE_BITS;
 
-// The trampoline is placed in the last page.
+// The paging scheme is SV39 by default, or SV48 with the feature `sv48`. The
+// virtual address consists of the VPN of each level and the page offset.
+#[cfg(not(feature = "sv48"))]
+pub const PAGE_TABLE_LEVELS: usize = 3;
+#[cfg(feature = "sv48")]
+pub const PAGE_TABLE_LEVELS: usize = 4;
+pub const VA_BITS_PER_LEVEL: usize = 9;
+pub const VA_WIDTH: usize =
+    PAGE_TABLE_LEVELS * VA_BITS_PER_LEVEL + PAGE_SIZE_BITS;
+
+// The user stack is placed at the end of the lower half of the address space,
+// so that the heap can grow right after the ELF segments.
+pub const USER_STACK_TOP: usize = 1 << (VA_WIDTH - 1);
+// The kernel picks the address for mmap from this range if it's not given,
+// the end is the guard page below the user stack.
+pub const MMAP_BASE: usize = 0x4000_0000;
+pub const MMAP_END: usize = USER_STACK_TOP - USER_STACK_SIZE - PAGE_SIZE;
+
+// The trampoline is placed in the last page, which is the same in the
+// sign-extended form for any paging scheme.
 pub const TRAMPOLINE_ADDR: usize = usize::MAX - PAGE_SIZE + 1;
 // The trap context is placed in the second last page.
 pub const TRAP_CONTEXT_ADDR: usize = TRAMPOLINE_ADDR - PAGE_SIZE;
 
-/// Return (bottom, top) of a kernel stack in kernel space.
-pub fn kernel_stack_position(app_id: usize) -> (usize, usize) {
-    let top = TRAP_CONTEXT_ADDR - app_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+/// Return (bottom, top) of the kernel stack for a pid in kernel space. Each
+/// stack is followed by a guard page.
+pub fn kernel_stack_position(pid: usize) -> (usize, usize) {
+    let top = TRAP_CONTEXT_ADDR - pid * (KERNEL_STACK_SIZE + PAGE_SIZE);
     let bottom = top - KERNEL_STACK_SIZE;
     (bottom, top)
 }
@@ -1,4 +1,4 @@
-use crate::sbi::console_putchar;
+use crate::sbi::{console_getchar, console_putchar};
 use core::fmt::{self, Write};
 
 struct Stdout;
@@ -12,6 +12,16 @@ impl Write for Stdout {
     }
 }
 
+// Returns the byte from the console input if there is any.
+pub fn getchar() -> Option<u8> {
+    match console_getchar() {
+        // The SBI returns -1 when there is no input, while some
+        // implementations return 0 instead.
+        0 | usize::MAX => None,
+        c => Some(c as u8),
+    }
+}
+
 pub fn print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
 }
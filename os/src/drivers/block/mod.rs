@@ -0,0 +1,23 @@
+// The block devices, which are read and written in blocks of BLOCK_SIZE bytes.
+mod virtio_blk;
+
+use lazy_static::*;
+
+pub const BLOCK_SIZE: usize = 512;
+
+pub trait BlockDevice {
+    // Return None if the board has no such device.
+    fn probe() -> Option<Self>
+    where
+        Self: Sized;
+    fn num_blocks(&self) -> usize;
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+}
+
+type BlockDeviceImpl = virtio_blk::VirtIOBlock;
+
+lazy_static! {
+    pub static ref BLOCK_DEVICE: Option<BlockDeviceImpl> =
+        BlockDeviceImpl::probe();
+}
@@ -0,0 +1,114 @@
+// The virtio block device over MMIO, e.g. `-device virtio-blk-device` in QEMU.
+// The requests are polled until they complete, no interrupt is involved.
+use super::BlockDevice;
+use crate::board::BOARD_INFO;
+use crate::mm::{
+    frame_alloc_contiguous, virt_to_phys, FrameTracker, PhysAddr, PhysPageNum,
+};
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
+
+// The registers of virtio-mmio, check the virtio spec 4.2.2 for details.
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+const VIRTIO_MAGIC_OFFSET: usize = 0x000;
+const VIRTIO_DEVICE_ID_OFFSET: usize = 0x008;
+const VIRTIO_DEVICE_ID_BLOCK: u32 = 2;
+// The capacity in 512-byte sectors is the first field of the configuration.
+const VIRTIO_CAPACITY_OFFSET: usize = 0x100;
+
+pub struct VirtIOBlock {
+    blk: UPSafeCell<VirtIOBlk<'static, VirtioHal>>,
+    num_blocks: usize,
+}
+
+lazy_static! {
+    // The frames of the virtqueues, which live as long as the device.
+    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> =
+        unsafe { UPSafeCell::new(Vec::new()) };
+}
+
+impl BlockDevice for VirtIOBlock {
+    // Takes the first virtio-mmio device which is a block device, the
+    // registers are mapped identically in the kernel space.
+    fn probe() -> Option<Self> {
+        let base = BOARD_INFO
+            .exclusive_access()
+            .virtio_mmio
+            .iter()
+            .map(|&(base, _)| base)
+            .find(|&base| unsafe {
+                ((base + VIRTIO_MAGIC_OFFSET) as *const u32).read_volatile()
+                    == VIRTIO_MAGIC
+                    && ((base + VIRTIO_DEVICE_ID_OFFSET) as *const u32)
+                        .read_volatile()
+                        == VIRTIO_DEVICE_ID_BLOCK
+            })?;
+        let num_blocks = unsafe {
+            ((base + VIRTIO_CAPACITY_OFFSET) as *const u64).read_volatile()
+        } as usize;
+        let blk = VirtIOBlk::<VirtioHal>::new(unsafe {
+            &mut *(base as *mut VirtIOHeader)
+        })
+        .expect("Failed to initialize the virtio block device");
+        println!(
+            "[kernel] Found virtio block device at {:#x} with {} blocks",
+            base, num_blocks
+        );
+        Some(Self {
+            blk: unsafe { UPSafeCell::new(blk) },
+            num_blocks,
+        })
+    }
+
+    fn num_blocks(&self) -> usize {
+        self.num_blocks
+    }
+
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.blk
+            .exclusive_access()
+            .read_block(block_id, buf)
+            .expect("Error when reading VirtIOBlk");
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.blk
+            .exclusive_access()
+            .write_block(block_id, buf)
+            .expect("Error when writing VirtIOBlk");
+    }
+}
+
+pub struct VirtioHal;
+
+impl Hal for VirtioHal {
+    fn dma_alloc(pages: usize) -> usize {
+        let frames = frame_alloc_contiguous(pages)
+            .expect("Cannot allocate the DMA buffer for virtio");
+        let pa = PhysAddr::from(frames[0].0).0;
+        QUEUE_FRAMES.exclusive_access().extend(frames);
+        pa
+    }
+
+    fn dma_dealloc(pa: usize, pages: usize) -> i32 {
+        let start: PhysPageNum = PhysAddr::from(pa).floor();
+        // The frames are deallocated once they're dropped.
+        QUEUE_FRAMES
+            .exclusive_access()
+            .retain(|frame| !(start.0..start.0 + pages).contains(&frame.0 .0));
+        0
+    }
+
+    // The physical memory is mapped identically in the kernel space.
+    fn phys_to_virt(addr: usize) -> usize {
+        addr
+    }
+
+    // The buffer might be on the kernel stack, which is not mapped
+    // identically.
+    fn virt_to_phys(vaddr: usize) -> usize {
+        virt_to_phys(vaddr).expect("The virtio buffer is not mapped")
+    }
+}
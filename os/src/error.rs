@@ -1,10 +1,51 @@
 use alloc::string::String;
 use thiserror_no_std::Error;
 
+// The error numbers returned to user space as negative values, which are the
+// same as Linux.
+pub const ENOENT: isize = 2;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EINVAL: isize = 22;
+pub const ENOSYS: isize = 38;
+
 #[derive(Error, Debug)]
 pub enum KernelError {
     #[error("invalid argument error: `{0}`")]
     InvalidArgument(String),
+    #[error("not found error: `{0}`")]
+    NotFound(String),
+    #[error("bad file descriptor: {0}")]
+    BadFileDescriptor(usize),
+    #[error("no child process")]
+    NoChild,
+    #[error("try again later")]
+    WouldBlock,
+    #[error("bad address: {0:#x}")]
+    BadAddress(usize),
+    #[error("out of memory error: `{0}`")]
+    OutOfMemory(String),
+    #[error("unsupported error: `{0}`")]
+    Unsupported(String),
+}
+
+impl KernelError {
+    // Return the error number for user space.
+    pub fn errno(&self) -> isize {
+        match self {
+            KernelError::InvalidArgument(_) => EINVAL,
+            KernelError::NotFound(_) => ENOENT,
+            KernelError::BadFileDescriptor(_) => EBADF,
+            KernelError::NoChild => ECHILD,
+            KernelError::WouldBlock => EAGAIN,
+            KernelError::BadAddress(_) => EFAULT,
+            KernelError::OutOfMemory(_) => ENOMEM,
+            KernelError::Unsupported(_) => ENOSYS,
+        }
+    }
 }
 
 pub type Result<T> = core::result::Result<T, KernelError>;
@@ -18,5 +18,5 @@ fn panic(info: &PanicInfo) -> ! {
         stack_trace::print_stack_trace();
     }
 
-    shutdown()
+    shutdown(true)
 }
@@ -1,4 +1,6 @@
+use alloc::vec::Vec;
 use core::arch::global_asm;
+use lazy_static::*;
 
 global_asm!(include_str!("link_app.S"));
 pub fn get_num_app() -> usize {
@@ -29,3 +31,45 @@ pub fn get_app_data(app_id: usize) -> &'static [u8] {
         )
     }
 }
+
+lazy_static! {
+    // The names of applications, which are listed in the same order as the
+    // applications in _app_names.
+    static ref APP_NAMES: Vec<&'static str> = {
+        extern "C" {
+            fn _app_names();
+        }
+        let num_app = get_num_app();
+        let mut start = _app_names as usize as *const u8;
+        let mut names = Vec::new();
+        unsafe {
+            for _ in 0..num_app {
+                let mut end = start;
+                while end.read_volatile() != b'\0' {
+                    end = end.add(1);
+                }
+                let name = core::slice::from_raw_parts(
+                    start,
+                    end as usize - start as usize,
+                );
+                names.push(core::str::from_utf8(name).unwrap());
+                start = end.add(1);
+            }
+        }
+        names
+    };
+}
+
+pub fn get_app_data_by_name(name: &str) -> Option<&'static [u8]> {
+    (0..get_num_app())
+        .find(|&app_id| APP_NAMES[app_id] == name)
+        .map(get_app_data)
+}
+
+pub fn list_apps() {
+    println!("/**** APPS ****");
+    for app in APP_NAMES.iter() {
+        println!("{}", app);
+    }
+    println!("**************/");
+}
@@ -3,9 +3,11 @@
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
 #![feature(drain_filter)]
+mod board;
 mod config;
 #[macro_use]
 mod console;
+mod drivers;
 mod error;
 mod lang_items;
 mod loader;
@@ -27,8 +29,10 @@ extern crate bitflags;
 use core::arch::global_asm;
 global_asm!(include_str!("entry.asm"));
 
+// The SBI passes the id of the hart in a0 and the physical address of the
+// device tree in a1.
 #[no_mangle]
-pub fn rust_main() -> ! {
+pub fn rust_main(_hart_id: usize, dtb_pa: usize) -> ! {
     clear_bss();
     if cfg!(debug_assertions) {
         println!("[kernel] Debugging enabled");
@@ -37,13 +41,17 @@ pub fn rust_main() -> ! {
     }
     println!("[kernel] Initializing trap handling");
     trap::init();
+    mm::init_heap();
+    println!("[kernel] Parsing device tree at {:#x}", dtb_pa);
+    board::init(dtb_pa);
     println!("[kernel] Initializing memory management");
     mm::init();
     println!("[kernel] Setting up timer interrupt");
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
+    task::add_initproc();
     println!("[kernel] Start running tasks");
-    task::run_first_task();
+    task::run_tasks();
 }
 
 fn clear_bss() {
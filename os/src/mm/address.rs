@@ -5,12 +5,11 @@ use crate::utils::*;
 use core::fmt::{self, Debug, Formatter};
 use core::ops;
 
-const PA_WIDTH_SV39: usize = 56;
-const VA_WIDTH_SV39: usize = 39;
-const PPN_WIDTH_SV39: usize = PA_WIDTH_SV39 - PAGE_SIZE_BITS;
-const VA_LEVEL_SV39: usize = 3;
-const VA_BITS_PER_LEVEL_SV39: usize = 9;
-const VPN_WIDTH_SV39: usize = VA_LEVEL_SV39 * VA_BITS_PER_LEVEL_SV39;
+// The widths are the same for SV39 and SV48 except the virtual address, which
+// depends on the levels of page table.
+const PA_WIDTH: usize = 56;
+const PPN_WIDTH: usize = PA_WIDTH - PAGE_SIZE_BITS;
+const VPN_WIDTH: usize = PAGE_TABLE_LEVELS * VA_BITS_PER_LEVEL;
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct PhysAddr(pub usize);
@@ -65,22 +64,22 @@ impl VirtPageNumRange {
 // usize -> T: usize.into()
 impl From<usize> for VirtPageNum {
     fn from(v: usize) -> Self {
-        Self(v & ((1usize << VPN_WIDTH_SV39) - 1))
+        Self(v & ((1usize << VPN_WIDTH) - 1))
     }
 }
 impl From<usize> for VirtAddr {
     fn from(v: usize) -> Self {
-        Self(v & ((1usize << VA_WIDTH_SV39) - 1))
+        Self(v & ((1usize << VA_WIDTH) - 1))
     }
 }
 impl From<usize> for PhysAddr {
     fn from(v: usize) -> Self {
-        Self(v & ((1usize << PA_WIDTH_SV39) - 1))
+        Self(v & ((1usize << PA_WIDTH) - 1))
     }
 }
 impl From<usize> for PhysPageNum {
     fn from(v: usize) -> Self {
-        Self(v & ((1usize << PPN_WIDTH_SV39) - 1))
+        Self(v & ((1usize << PPN_WIDTH) - 1))
     }
 }
 impl From<PhysAddr> for usize {
@@ -164,12 +163,12 @@ impl PhysPageNum {
     }
 }
 impl VirtPageNum {
-    pub fn indexes(&self) -> [usize; VA_LEVEL_SV39] {
+    pub fn indexes(&self) -> [usize; PAGE_TABLE_LEVELS] {
         let mut vpn = self.0;
-        let mut idx = [0usize; VA_LEVEL_SV39];
-        for i in 0..VA_LEVEL_SV39 {
-            idx[i] = vpn & ((1 << VA_BITS_PER_LEVEL_SV39) - 1);
-            vpn >>= VA_BITS_PER_LEVEL_SV39;
+        let mut idx = [0usize; PAGE_TABLE_LEVELS];
+        for i in 0..PAGE_TABLE_LEVELS {
+            idx[i] = vpn & ((1 << VA_BITS_PER_LEVEL) - 1);
+            vpn >>= VA_BITS_PER_LEVEL;
         }
         idx
     }
@@ -0,0 +1,103 @@
+// The address space identifier(ASID) allocator. Each memory set has its own
+// ASID which is a part of the satp token, so that the TLB entries of different
+// address spaces are told apart and switching satp needs no flush.
+//
+// ASID 0 is shared by the memory sets which cannot have their own, i.e. the
+// ones created before the ASIDs are probed, or after all of them are in use,
+// or every memory set if the hardware does not support ASID at all. The TLB
+// is flushed on every satp switch to ASID 0 in trap.S.
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use core::arch::asm;
+use lazy_static::*;
+use riscv::register::satp;
+
+pub const SATP_ASID_SHIFT: usize = 44;
+pub const SATP_ASID_MASK: usize = 0xffff;
+
+struct AsidAllocator {
+    // The largest ASID which the hardware supports.
+    max: usize,
+    // The smallest ASID which has never been allocated.
+    current: usize,
+    // A list contains recycled ASIDs.
+    recycled: Vec<usize>,
+}
+
+pub struct AsidHandle(pub usize);
+
+lazy_static! {
+    static ref ASID_ALLOCATOR: UPSafeCell<AsidAllocator> = unsafe {
+        UPSafeCell::new(AsidAllocator {
+            max: 0,
+            current: 1,
+            recycled: Vec::new(),
+        })
+    };
+}
+
+// The ASID bits of satp are WARL, the bits which stick after writing all ones
+// are the ones that the hardware supports. It's probed once the kernel space
+// is active, since writing satp with MODE Bare and a non-zero ASID is
+// unspecified. The current satp is restored right after probing.
+pub fn init() {
+    let old = satp::read().bits();
+    let max = unsafe {
+        satp::write(old | (SATP_ASID_MASK << SATP_ASID_SHIFT));
+        let max = (satp::read().bits() >> SATP_ASID_SHIFT) & SATP_ASID_MASK;
+        satp::write(old);
+        max
+    };
+    // The TLB entries filled while probing are tagged by the probed ASID.
+    flush_tlb(max, None);
+    println!("[kernel] ASID is supported up to {}", max);
+    ASID_ALLOCATOR.exclusive_access().max = max;
+}
+
+// We only expose alloc method and we depend on RAII scheme to dealloc.
+pub fn asid_alloc() -> AsidHandle {
+    ASID_ALLOCATOR.exclusive_access().alloc()
+}
+
+// Flushes the TLB entries of the given ASID, or only the ones of the given
+// virtual address.
+pub fn flush_tlb(asid: usize, va: Option<usize>) {
+    unsafe {
+        match va {
+            Some(va) => asm!("sfence.vma {}, {}", in(reg) va, in(reg) asid),
+            None => asm!("sfence.vma zero, {}", in(reg) asid),
+        }
+    }
+}
+
+impl AsidAllocator {
+    fn alloc(&mut self) -> AsidHandle {
+        if let Some(asid) = self.recycled.pop() {
+            // The stale TLB entries of the previous owner must be gone.
+            flush_tlb(asid, None);
+            AsidHandle(asid)
+        } else if self.current <= self.max {
+            self.current += 1;
+            AsidHandle(self.current - 1)
+        } else {
+            // All ASIDs are in use, or not probed yet.
+            AsidHandle(0)
+        }
+    }
+    // ASID 0 is shared hence never recycled.
+    fn dealloc(&mut self, asid: usize) {
+        if asid == 0 {
+            return;
+        }
+        if asid >= self.current || self.recycled.iter().any(|&v| v == asid) {
+            panic!("ASID {} has not been allocated!", asid);
+        }
+        self.recycled.push(asid);
+    }
+}
+
+impl Drop for AsidHandle {
+    fn drop(&mut self) {
+        ASID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
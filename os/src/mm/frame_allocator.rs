@@ -1,14 +1,24 @@
 // The frame allocator manages the allocation and deallocation of physical page.
 use super::address::*;
-use crate::config::MEMORY_END;
+use super::swap::reclaim_frames;
+use crate::board::memory_end;
+use crate::error::{KernelError, Result};
 use crate::sync::UPSafeCell;
+use alloc::collections::BTreeSet;
+use alloc::string::ToString;
+use alloc::vec;
 use alloc::vec::Vec;
 use lazy_static::*;
 
 trait FrameAllocator {
     fn new() -> Self;
     fn alloc(&mut self) -> Option<PhysPageNum>;
+    // Allocates the given number of physically contiguous pages, return the
+    // first one. Each of them is deallocated separately.
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum>;
     fn dealloc(&mut self, ppn: PhysPageNum);
+    // Return the number of pages which are free.
+    fn free_pages(&self) -> usize;
 }
 #[derive(Debug)]
 pub struct FrameTracker(pub PhysPageNum);
@@ -20,7 +30,24 @@ pub struct StackFrameAllocator {
     recycled: Vec<PhysPageNum>,
 }
 
-type FrameAllocatorImpl = StackFrameAllocator;
+// The largest block of the buddy allocator has 2^MAX_ORDER pages.
+const MAX_ORDER: usize = 12;
+
+pub struct BuddyFrameAllocator {
+    // The range of PhysPages managed by the allocator.
+    start: usize,
+    end: usize,
+    // The free blocks of 2^order pages for each order, which are aligned to
+    // their size in physical memory, hence the buddy of a block is found by
+    // flipping a bit of its PPN.
+    free_lists: [BTreeSet<usize>; MAX_ORDER + 1],
+    // One bit for each page in the range which is set if it's allocated.
+    allocated: Vec<u64>,
+    // The number of pages in the free lists.
+    free: usize,
+}
+
+type FrameAllocatorImpl = BuddyFrameAllocator;
 
 lazy_static! {
     pub static ref FRAME_ALLOCATOR: UPSafeCell<FrameAllocatorImpl> =
@@ -33,16 +60,43 @@ pub fn init_frame_allocator() {
     }
     FRAME_ALLOCATOR.exclusive_access().init(
         PhysAddr::from(ekernel as usize).ceil(),
-        PhysAddr::from(MEMORY_END).floor(),
+        PhysAddr::from(memory_end()).floor(),
     );
 }
 
 // We only expose alloc method and we depend on RAII scheme to dealloc.
-pub fn frame_alloc() -> Option<FrameTracker> {
+// Once the frames run out, some user pages are swapped out to make room; It
+// fails if nothing can be reclaimed.
+pub fn frame_alloc() -> Result<FrameTracker> {
+    let ppn = FRAME_ALLOCATOR.exclusive_access().alloc();
+    ppn.or_else(|| {
+        reclaim_frames(1);
+        FRAME_ALLOCATOR.exclusive_access().alloc()
+    })
+    .map(|ppn| FrameTracker::new(ppn))
+    .ok_or_else(|| KernelError::OutOfMemory("No frame is left".to_string()))
+}
+
+pub fn free_frames() -> usize {
+    FRAME_ALLOCATOR.exclusive_access().free_pages()
+}
+
+// Allocates physically contiguous pages, e.g. for DMA buffers or huge pages.
+// The frames are in the order of their PPN.
+pub fn frame_alloc_contiguous(pages: usize) -> Option<Vec<FrameTracker>> {
     FRAME_ALLOCATOR
         .exclusive_access()
-        .alloc()
-        .map(|ppn| FrameTracker::new(ppn))
+        .alloc_contiguous(pages)
+        .map(|ppn| (0..pages).map(|i| FrameTracker::new(ppn + i)).collect())
+}
+
+// Takes contiguous pages for the kernel heap, which are never given back.
+// Return None if the frame allocator is in use, i.e. the heap grows while the
+// frame allocator itself is allocating from the heap.
+pub fn frame_alloc_for_heap(pages: usize) -> Option<PhysPageNum> {
+    FRAME_ALLOCATOR
+        .try_exclusive_access()?
+        .alloc_contiguous(pages)
 }
 
 fn frame_dealloc(ppn: PhysPageNum) {
@@ -97,6 +151,16 @@ impl FrameAllocator for StackFrameAllocator {
         }
     }
 
+    // Only the PhysPages which are never allocated are contiguous.
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        if pages == 0 || self.end.0 - self.current.0 < pages {
+            return None;
+        }
+        let result = self.current;
+        self.current = self.current + pages;
+        Some(result)
+    }
+
     fn dealloc(&mut self, ppn: PhysPageNum) {
         if ppn >= self.current
             || self.recycled.iter().find(|&v| *v == ppn).is_some()
@@ -105,4 +169,155 @@ impl FrameAllocator for StackFrameAllocator {
         }
         self.recycled.push(ppn);
     }
+
+    fn free_pages(&self) -> usize {
+        self.end.0 - self.current.0 + self.recycled.len()
+    }
+}
+
+// Return the floor of log2 of a positive number.
+fn log2(n: usize) -> usize {
+    (usize::BITS - 1 - n.leading_zeros()) as usize
+}
+
+impl BuddyFrameAllocator {
+    pub fn init(&mut self, start: PhysPageNum, end: PhysPageNum) {
+        debug!("Frame allocator start: {:?} end:{:?}", start, end);
+        self.start = start.0;
+        self.end = end.0;
+        self.allocated = vec![0; (end.0 - start.0 + 63) / 64];
+        self.free_range(start.0, end.0);
+        self.free = end.0 - start.0;
+    }
+
+    // Frees the pages in [start, end) as the largest aligned blocks.
+    fn free_range(&mut self, mut start: usize, end: usize) {
+        while start < end {
+            let order = (start.trailing_zeros() as usize)
+                .min(log2(end - start))
+                .min(MAX_ORDER);
+            self.free_block(start, order);
+            start += 1 << order;
+        }
+    }
+
+    // Puts a block back to the free lists, it's merged with its buddy
+    // repeatedly as long as the buddy is free as a whole.
+    fn free_block(&mut self, mut ppn: usize, mut order: usize) {
+        while order < MAX_ORDER {
+            let buddy = ppn ^ (1 << order);
+            if !self.free_lists[order].remove(&buddy) {
+                break;
+            }
+            ppn = ppn.min(buddy);
+            order += 1;
+        }
+        self.free_lists[order].insert(ppn);
+    }
+
+    // Takes a free block of 2^order pages, a larger block is split if there
+    // is no such one.
+    fn alloc_block(&mut self, order: usize) -> Option<usize> {
+        let found =
+            (order..=MAX_ORDER).find(|&o| !self.free_lists[o].is_empty())?;
+        let ppn = *self.free_lists[found].iter().next().unwrap();
+        self.free_lists[found].remove(&ppn);
+        // The upper halves are left in the free lists.
+        for o in (order..found).rev() {
+            self.free_lists[o].insert(ppn + (1 << o));
+        }
+        Some(ppn)
+    }
+
+    // Return whether the page was allocated before it's marked as the given
+    // state.
+    fn mark(&mut self, ppn: usize, allocated: bool) -> bool {
+        assert!(
+            ppn >= self.start && ppn < self.end,
+            "Frame ppn {:#x} is out of range",
+            ppn
+        );
+        let (idx, bit) = ((ppn - self.start) / 64, (ppn - self.start) % 64);
+        let was_allocated = self.allocated[idx] & (1 << bit) != 0;
+        if allocated {
+            self.allocated[idx] |= 1 << bit;
+        } else {
+            self.allocated[idx] &= !(1 << bit);
+        }
+        was_allocated
+    }
+}
+
+impl FrameAllocator for BuddyFrameAllocator {
+    fn new() -> Self {
+        BuddyFrameAllocator {
+            start: 0,
+            end: 0,
+            free_lists: Default::default(),
+            allocated: Vec::new(),
+            free: 0,
+        }
+    }
+
+    fn alloc(&mut self) -> Option<PhysPageNum> {
+        self.alloc_contiguous(1)
+    }
+
+    // The block is rounded up to a power of 2 pages, the rest of it is freed
+    // right away.
+    fn alloc_contiguous(&mut self, pages: usize) -> Option<PhysPageNum> {
+        if pages == 0 || pages > 1 << MAX_ORDER {
+            return None;
+        }
+        let order = log2(pages.next_power_of_two());
+        let ppn = self.alloc_block(order)?;
+        self.free_range(ppn + pages, ppn + (1 << order));
+        for page in ppn..ppn + pages {
+            self.mark(page, true);
+        }
+        self.free -= pages;
+        Some(PhysPageNum::from(ppn))
+    }
+
+    // The double free is detected by the bitmap in O(1), then the page is
+    // merged with its buddies in O(log n).
+    fn dealloc(&mut self, ppn: PhysPageNum) {
+        if !self.mark(ppn.0, false) {
+            panic!("Frame ppn {:?} has not been allocated!", ppn);
+        }
+        self.free_block(ppn.0, 0);
+        self.free += 1;
+    }
+
+    fn free_pages(&self) -> usize {
+        self.free
+    }
+}
+
+#[allow(unused)]
+pub fn frame_allocator_test() {
+    let mut frames = Vec::new();
+    for _ in 0..5 {
+        frames.push(frame_alloc().unwrap());
+    }
+    let ppns: Vec<PhysPageNum> = frames.iter().map(|frame| frame.0).collect();
+    frames.clear();
+    // The freed pages are merged back, hence the same pages are reused.
+    for _ in 0..5 {
+        let frame = frame_alloc().unwrap();
+        assert!(ppns.contains(&frame.0));
+        frames.push(frame);
+    }
+    drop(frames);
+    let frames = frame_alloc_contiguous(512).unwrap();
+    // Rounded up to 512 pages, the block is aligned to 2MiB.
+    assert_eq!(frames[0].0 .0 % 512, 0);
+    for (i, frame) in frames.iter().enumerate() {
+        assert_eq!(frame.0 .0, frames[0].0 .0 + i);
+    }
+    drop(frames);
+    let frames = frame_alloc_contiguous(3).unwrap();
+    assert_eq!(frames[2].0 .0, frames[0].0 .0 + 2);
+    drop(frames);
+    println!("frame_allocator_test passed!");
 }
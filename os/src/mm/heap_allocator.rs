@@ -1,10 +1,118 @@
-use crate::config::KERNEL_HEAP_SIZE;
-use linked_list_allocator::LockedHeap;
+// The kernel heap starts with a small space in .bss, then it grows by taking
+// contiguous frames from the frame allocator once it runs low. The frames are
+// accessible right away since the physical memory is mapped identically.
+use super::address::PhysAddr;
+use super::frame_allocator::frame_alloc_for_heap;
+use crate::config::{KERNEL_HEAP_SIZE, PAGE_SIZE};
+use buddy_system_allocator::LockedHeap;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+// The heap grows by at least this many pages each time.
+const HEAP_GROW_PAGES: usize = 64;
+// The heap grows once the free space is less than it, so that the frame
+// allocator always has some heap to allocate from while the heap grows.
+const HEAP_RESERVE: usize = 64 * 1024;
+
+pub struct KernelHeap {
+    heap: LockedHeap<32>,
+    // Set while the heap is growing, the allocations made by the frame
+    // allocator meanwhile are served from the reserve.
+    growing: AtomicBool,
+}
+
+// The usage of the kernel heap in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    // The bytes requested by the allocations.
+    pub user: usize,
+    // The bytes actually taken, which are rounded up to the power of 2.
+    pub actual: usize,
+    pub total: usize,
+}
+
+impl KernelHeap {
+    const fn new() -> Self {
+        Self {
+            heap: LockedHeap::empty(),
+            growing: AtomicBool::new(false),
+        }
+    }
+
+    fn free_bytes(&self) -> usize {
+        let heap = self.heap.lock();
+        heap.stats_total_bytes() - heap.stats_alloc_actual()
+    }
+
+    // Adds at least the given bytes to the heap, return false if there is no
+    // memory or the heap is growing already.
+    fn grow(&self, bytes: usize) -> bool {
+        if self.growing.swap(true, Ordering::Acquire) {
+            return false;
+        }
+        // The block of the buddy allocator must be aligned to its size, which
+        // is the case for the contiguous frames as long as it's a power of 2.
+        let pages = ((bytes + PAGE_SIZE - 1) / PAGE_SIZE)
+            .next_power_of_two()
+            .max(HEAP_GROW_PAGES);
+        let grown = match frame_alloc_for_heap(pages) {
+            Some(ppn) => {
+                let start = PhysAddr::from(ppn).0;
+                debug!(
+                    "Growing kernel heap by [{:#x}, {:#x})",
+                    start,
+                    start + pages * PAGE_SIZE
+                );
+                unsafe {
+                    self.heap
+                        .lock()
+                        .add_to_heap(start, start + pages * PAGE_SIZE);
+                }
+                true
+            }
+            None => false,
+        };
+        self.growing.store(false, Ordering::Release);
+        grown
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // The lock is released before growing, since the frame allocator
+        // might allocate from the heap as well.
+        let mut result = self.heap.lock().alloc(layout);
+        if result.is_err() && self.grow(layout.size().max(layout.align())) {
+            result = self.heap.lock().alloc(layout);
+        }
+        if self.free_bytes() < HEAP_RESERVE {
+            self.grow(0);
+        }
+        result.map_or(null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.heap
+            .lock()
+            .dealloc(NonNull::new_unchecked(ptr), layout);
+    }
+}
+
 #[global_allocator]
-static HEAP_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static HEAP_ALLOCATOR: KernelHeap = KernelHeap::new();
 
 static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 
+pub fn heap_stats() -> HeapStats {
+    let heap = HEAP_ALLOCATOR.heap.lock();
+    HeapStats {
+        user: heap.stats_alloc_user(),
+        actual: heap.stats_alloc_actual(),
+        total: heap.stats_total_bytes(),
+    }
+}
+
 fn test_heap() {
     use alloc::boxed::Box;
     use alloc::vec::Vec;
@@ -32,13 +140,35 @@ fn test_heap() {
 pub fn init_heap() {
     unsafe {
         HEAP_ALLOCATOR
+            .heap
             .lock()
-            .init(HEAP_SPACE.as_ptr() as *mut u8, KERNEL_HEAP_SIZE);
+            .init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
     }
     test_heap();
 }
 
+// Allocates more than the initial heap, which must be served by the frames.
+#[allow(unused)]
+pub fn heap_grow_test() {
+    use alloc::vec::Vec;
+    let before = heap_stats();
+    let v: Vec<u8> = vec![1; KERNEL_HEAP_SIZE];
+    assert!(v.iter().all(|&b| b == 1));
+    let after = heap_stats();
+    assert!(after.total > before.total);
+    assert!(after.user >= before.user + KERNEL_HEAP_SIZE);
+    drop(v);
+    println!(
+        "[kernel] Test heap growing passed, heap stats: {:?}",
+        heap_stats()
+    );
+}
+
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
-    panic!("Heap allocation error, layout = {:?}", layout);
+    panic!(
+        "Heap allocation error, layout = {:?}, heap stats = {:?}",
+        layout,
+        heap_stats()
+    );
 }
@@ -3,29 +3,39 @@
 
 use super::{
     address::*,
+    asid::{asid_alloc, AsidHandle},
     frame_allocator::{frame_alloc, FrameTracker},
-    page_table::{PTEFlags, PageTable},
+    page_table::{pages_at_level, PTEFlags, PageTable},
+    swap::SwapSlot,
 };
 use crate::error::Result;
 use crate::utils::StepByOne;
 use crate::{
+    board::{memory_end, BOARD_INFO},
     config::{
-        MEMORY_END, PAGE_SIZE, TRAMPOLINE_ADDR, TRAP_CONTEXT_ADDR,
-        USER_STACK_SIZE,
+        MMAP_BASE, MMAP_END, PAGE_SIZE, PAGE_TABLE_LEVELS, TRAMPOLINE_ADDR,
+        TRAP_CONTEXT_ADDR, USER_STACK_SIZE, USER_STACK_TOP,
     },
     error::KernelError,
 };
 use alloc::collections::{BTreeMap, LinkedList};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::arch::asm;
-use core::cmp::max;
+use core::cmp::{max, min};
 use riscv::register::satp;
 
 pub struct MemorySet {
     page_table: PageTable,
+    // The ASID is released after the page table when the memory set is
+    // dropped.
+    asid: AsidHandle,
     // A pair of values, the bool indicates whether the pair is droppable from
     // the areas list. The elements are sorted by the VPN range in each MapArea
     // in ascending order and assumed there is no overlap.
     areas: LinkedList<(MapArea, bool)>,
+    // The page replacement goes on from this VPN next time.
+    clock_hand: VirtPageNum,
 }
 // The MapArea includes the information about a consecutive memory segment
 // given the context(page table).
@@ -35,14 +45,24 @@ pub struct MapArea {
     // The mapping schema for this map area.
     mapping: Mapping,
     map_perm: MapPermission,
+    // Whether the pages might be shared with another memory set since fork;
+    // The writable ones are mapped read-only and copied on the first write.
+    cow: bool,
+    // Whether the pages are shared with the forked memory sets for good, i.e.
+    // MAP_SHARED; The writes are visible to each other instead of copied.
+    shared: bool,
+    // The pages which are swapped out, they're not in the mapping meanwhile.
+    swapped: BTreeMap<VirtPageNum, SwapSlot>,
 }
 
 #[derive(Debug)]
 pub enum Mapping {
     // If the mapping schema is identical, VPN==PPN.
     Identical,
-    // If the mapping schema is Framed, allocating a frame as PPN for it.
-    Framed(BTreeMap<VirtPageNum, FrameTracker>),
+    // If the mapping schema is Framed, allocating a frame as PPN for it. The
+    // frames are reference counted since they might be shared by copy on
+    // write.
+    Framed(BTreeMap<VirtPageNum, Arc<FrameTracker>>),
 }
 
 bitflags! {
@@ -75,11 +95,14 @@ impl Mapping {
 }
 
 impl MemorySet {
-    pub fn new() -> Self {
-        Self {
-            page_table: PageTable::new(),
+    pub fn new() -> Result<Self> {
+        let asid = asid_alloc();
+        Ok(Self {
+            page_table: PageTable::new(asid.0)?,
+            asid,
             areas: LinkedList::new(),
-        }
+            clock_hand: VirtPageNum(0),
+        })
     }
     pub fn drop_area(&mut self, vpn_range: VirtPageNumRange) -> Result<()> {
         if let Some((mut area, _)) = self
@@ -91,8 +114,7 @@ impl MemorySet {
         {
             debug!("Dropping area: {:?}", area);
             for vpn in area.vpn_range {
-                area.mapping.unmap(vpn);
-                self.page_table.unmap(vpn);
+                area.unmap_one(&mut self.page_table, vpn);
             }
             Ok(())
         } else {
@@ -103,14 +125,194 @@ impl MemorySet {
         }
     }
 
-    pub fn push_area(
+    // Removes the pages in the given range from the droppable areas, i.e. the
+    // ones created by mmap; The areas which are partially covered are split.
+    // Every page in the range must belong to one of the droppable areas.
+    pub fn unmap_range(&mut self, vpn_range: VirtPageNumRange) -> Result<()> {
+        self.check_covered(vpn_range, |_, droppable| droppable)?;
+        let mut areas = LinkedList::new();
+        while let Some((area, droppable)) = self.areas.pop_front() {
+            if !droppable {
+                areas.push_back((area, droppable));
+                continue;
+            }
+            let (head, inside, tail) = area.split(vpn_range);
+            if let Some(head) = head {
+                areas.push_back((head, droppable));
+            }
+            if let Some(mut inside) = inside {
+                debug!("Unmapping area: {:?}", inside.vpn_range);
+                for vpn in inside.vpn_range {
+                    inside.unmap_one(&mut self.page_table, vpn);
+                }
+            }
+            if let Some(tail) = tail {
+                areas.push_back((tail, droppable));
+            }
+        }
+        self.areas = areas;
+        Ok(())
+    }
+
+    // Changes the permission of the pages in the given range, both in the
+    // areas and the page table; The areas which are partially covered are
+    // split, then the adjacent ones with the same permission are merged.
+    // Every page in the range must belong to one of the droppable areas, i.e.
+    // the ones created by mmap; The others, e.g. the heap which is resized as a
+    // whole by brk, must not be split.
+    pub fn protect_range(
         &mut self,
-        mut new_area: MapArea,
-        droppable: bool,
-        data: Option<&[u8]>,
+        vpn_range: VirtPageNumRange,
+        map_perm: MapPermission,
     ) -> Result<()> {
-        debug!("Pushing new area: {:?}", new_area);
-        // First, check if there is overlap with existing areas and find the place to insert.
+        self.check_covered(vpn_range, |_, droppable| droppable)?;
+        let mut areas = LinkedList::new();
+        while let Some((area, droppable)) = self.areas.pop_front() {
+            if !droppable {
+                areas.push_back((area, droppable));
+                continue;
+            }
+            let (head, inside, tail) = area.split(vpn_range);
+            if let Some(head) = head {
+                push_merged(&mut areas, head, droppable);
+            }
+            if let Some(mut inside) = inside {
+                debug!(
+                    "Protecting area: {:?} with {:?}",
+                    inside.vpn_range, map_perm
+                );
+                inside.protect(&mut self.page_table, map_perm);
+                push_merged(&mut areas, inside, droppable);
+            }
+            if let Some(tail) = tail {
+                push_merged(&mut areas, tail, droppable);
+            }
+        }
+        self.areas = areas;
+        Ok(())
+    }
+
+    // Checks every page in the range belongs to one of the areas accepted by
+    // the filter.
+    fn check_covered<F>(
+        &self,
+        vpn_range: VirtPageNumRange,
+        filter: F,
+    ) -> Result<()>
+    where
+        F: Fn(&MapArea, bool) -> bool,
+    {
+        let covered: usize = self
+            .areas
+            .iter()
+            .filter(|(area, droppable)| filter(area, *droppable))
+            .map(|(area, _)| {
+                let start =
+                    max(area.vpn_range.get_start(), vpn_range.get_start());
+                let end = min(area.vpn_range.get_end(), vpn_range.get_end());
+                end.0.saturating_sub(start.0)
+            })
+            .sum();
+        let expected = vpn_range
+            .get_end()
+            .0
+            .saturating_sub(vpn_range.get_start().0);
+        if covered == expected {
+            Ok(())
+        } else {
+            Err(KernelError::InvalidArgument(format!(
+                "The VPN range {:?} is not fully covered by the areas",
+                vpn_range
+            )))
+        }
+    }
+
+    // Grows or shrinks the area starting from the given VPN to the new end,
+    // i.e. the heap; The grown pages are populated on demand.
+    pub fn resize_area(
+        &mut self,
+        start: VirtPageNum,
+        new_end: VirtPageNum,
+    ) -> Result<()> {
+        let old_end = match self
+            .areas
+            .iter()
+            .find(|(area, _)| area.vpn_range.get_start() == start)
+        {
+            Some((area, _)) => area.vpn_range.get_end(),
+            None => {
+                return Err(KernelError::InvalidArgument(format!(
+                    "Cannot find the area starting from {:?}",
+                    start
+                )))
+            }
+        };
+        if new_end < start {
+            return Err(KernelError::InvalidArgument(format!(
+                "The new end {:?} is before the start {:?}",
+                new_end, start
+            )));
+        }
+        if new_end > old_end {
+            let grown = VirtPageNumRange::new(old_end, new_end);
+            if let Some((area, _)) = self.areas.iter().find(|(area, _)| {
+                area.vpn_range.get_start() != start
+                    && area.vpn_range.intersect(grown).is_some()
+            }) {
+                return Err(KernelError::InvalidArgument(format!(
+                    "The grown range {:?} conflicts with the existing one {:?}",
+                    grown, area.vpn_range
+                )));
+            }
+        }
+        let page_table = &mut self.page_table;
+        let (area, _) = self
+            .areas
+            .iter_mut()
+            .find(|(area, _)| area.vpn_range.get_start() == start)
+            .unwrap();
+        for vpn in VirtPageNumRange::new(new_end, old_end) {
+            area.unmap_one(page_table, vpn);
+        }
+        area.vpn_range = VirtPageNumRange::new(start, new_end);
+        Ok(())
+    }
+
+    // Return true if no area overlaps with the VPN range.
+    pub fn is_free(&self, vpn_range: VirtPageNumRange) -> bool {
+        self.areas
+            .iter()
+            .all(|(area, _)| area.vpn_range.intersect(vpn_range).is_none())
+    }
+
+    // Finds the lowest free VPN range with the given number of pages in
+    // [MMAP_BASE, MMAP_END).
+    pub fn find_free_range(
+        &self,
+        num_pages: usize,
+    ) -> Option<VirtPageNumRange> {
+        let mut ranges: Vec<VirtPageNumRange> =
+            self.areas.iter().map(|(area, _)| area.vpn_range).collect();
+        ranges.sort_by_key(|range| range.get_start());
+        let mut start = VirtAddr::from(MMAP_BASE).floor();
+        for range in ranges {
+            if range.get_end() <= start {
+                continue;
+            }
+            if range.get_start().0 >= start.0 + num_pages {
+                break;
+            }
+            start = range.get_end();
+        }
+        let end = VirtPageNum(start.0 + num_pages);
+        if end <= VirtAddr::from(MMAP_END).floor() {
+            Some(VirtPageNumRange::new(start, end))
+        } else {
+            None
+        }
+    }
+
+    fn check_overlap(&self, new_area: &MapArea) -> Result<()> {
         for (area, _) in self.areas.iter() {
             if let Some(overlap) = new_area.vpn_range.intersect(area.vpn_range)
             {
@@ -120,12 +322,28 @@ impl MemorySet {
                     )));
             }
         }
+        Ok(())
+    }
 
-        // Populate the area to the page table.
-        let pte_flags = PTEFlags::from_bits(new_area.map_perm.bits()).unwrap();
-        for vpn in new_area.vpn_range {
-            let ppn = new_area.mapping.map(vpn);
-            self.page_table.map(vpn, ppn, pte_flags);
+    pub fn push_area(
+        &mut self,
+        mut new_area: MapArea,
+        droppable: bool,
+        data: Option<&[u8]>,
+    ) -> Result<()> {
+        debug!("Pushing new area: {:?}", new_area);
+        // First, check if there is overlap with existing areas.
+        self.check_overlap(&new_area)?;
+
+        // Populate the area to the page table. If it fails halfway, the frames
+        // populated so far are released with the area, hence they're unmapped.
+        if let Err(err) = new_area.map_all(&mut self.page_table) {
+            if let Mapping::Framed(_) = new_area.mapping {
+                for vpn in new_area.vpn_range {
+                    new_area.unmap_one(&mut self.page_table, vpn);
+                }
+            }
+            return Err(err);
         }
         // Optionally, if there is data, copy it into the MapArea.
         if let Some(data) = data {
@@ -161,6 +379,207 @@ impl MemorySet {
         Ok(())
     }
 
+    // Reserves the area without allocating any frame, each page is populated
+    // by `handle_page_fault` on the first touch.
+    pub fn push_lazy_area(
+        &mut self,
+        new_area: MapArea,
+        droppable: bool,
+    ) -> Result<()> {
+        debug!("Pushing new lazy area: {:?}", new_area);
+        self.check_overlap(&new_area)?;
+        self.areas.push_back((new_area, droppable));
+        Ok(())
+    }
+
+    // Handles the page fault given the kind of access which triggers it, i.e.
+    // R, W or X. Either the page is reserved by a lazy area but not populated
+    // yet, or it's swapped out, or it's a write to a page shared by copy on
+    // write.
+    pub fn handle_page_fault(
+        &mut self,
+        vpn: VirtPageNum,
+        access: MapPermission,
+    ) -> Result<()> {
+        let page_table = &mut self.page_table;
+        let area = match self
+            .areas
+            .iter_mut()
+            .find(|(area, _)| area.vpn_range.contains(vpn))
+        {
+            Some((area, _)) => area,
+            None => {
+                return Err(KernelError::InvalidArgument(format!(
+                    "The page {:?} is not in any area",
+                    vpn
+                )))
+            }
+        };
+        if !area.map_perm.contains(access) {
+            return Err(KernelError::InvalidArgument(format!(
+                "The access {:?} to page {:?} is not allowed by {:?}",
+                access, vpn, area.map_perm
+            )));
+        }
+        match page_table.translate(vpn).filter(|pte| pte.is_valid()) {
+            None if area.swapped.contains_key(&vpn) => {
+                debug!("Swapping in page {:?}", vpn);
+                area.swap_in_one(page_table, vpn)
+            }
+            None => {
+                debug!(
+                    "Populating page {:?} of area: {:?}",
+                    vpn, area.vpn_range
+                );
+                area.map_one(page_table, vpn).map(|_| ())
+            }
+            Some(pte)
+                if area.cow
+                    && access == MapPermission::W
+                    && !pte.writable() =>
+            {
+                debug!("Copying on write page {:?}", vpn);
+                area.copy_on_write(page_table, vpn)
+            }
+            Some(_) => Err(KernelError::InvalidArgument(format!(
+                "The page {:?} is mapped already",
+                vpn
+            ))),
+        }
+    }
+
+    // Clones the user space for fork. The populated pages are shared with the
+    // copy instead of copied, and the writable ones are mapped read-only in
+    // both until either writes; The pages which are not populated yet are left
+    // lazy in the copy too, and the swapped out ones are copied in the swap
+    // area, which takes no frame.
+    pub fn from_existed_user(user_space: &mut MemorySet) -> Result<Self> {
+        let mut memory_set = Self::new()?;
+        memory_set.map_trampoline()?;
+        let page_table = &mut user_space.page_table;
+        for (area, droppable) in user_space.areas.iter_mut() {
+            let mut new_area = MapArea::from_another(area);
+            for vpn in area.vpn_range {
+                if let Some(slot) = area.swapped.get(&vpn) {
+                    new_area.swapped.insert(vpn, slot.duplicate()?);
+                    continue;
+                }
+                if page_table
+                    .translate(vpn)
+                    .filter(|pte| pte.is_valid())
+                    .is_none()
+                {
+                    continue;
+                }
+                if area.map_perm.contains(MapPermission::U) {
+                    area.share_one(
+                        page_table,
+                        &mut new_area,
+                        &mut memory_set.page_table,
+                        vpn,
+                    )?;
+                } else {
+                    // The pages only accessible by the kernel, i.e. the
+                    // TrapContext, are private to each task.
+                    let src_ppn = page_table.translate(vpn).unwrap().ppn();
+                    let dst_ppn =
+                        new_area.map_one(&mut memory_set.page_table, vpn)?;
+                    dst_ppn
+                        .get_bytes_array()
+                        .copy_from_slice(src_ppn.get_bytes_array());
+                }
+            }
+            memory_set.push_lazy_area(new_area, *droppable)?;
+        }
+        Ok(memory_set)
+    }
+
+    // Swaps out at most the given number of pages which are owned by this
+    // memory set alone, return how many are swapped out. The pages are visited
+    // in the order of VPN from the clock hand, the accessed ones are skipped
+    // once while their A is cleared.
+    pub fn swap_out(&mut self, pages: usize) -> usize {
+        let page_table = &mut self.page_table;
+        let mut vpns: Vec<VirtPageNum> = self
+            .areas
+            .iter()
+            .flat_map(|(area, _)| area.swappable_pages())
+            .collect();
+        vpns.sort();
+        let start = vpns
+            .iter()
+            .position(|&vpn| vpn >= self.clock_hand)
+            .unwrap_or(0);
+        vpns.rotate_left(start);
+        let mut swapped_out = 0;
+        // The second round finds the pages whose A is cleared in the first.
+        for &vpn in vpns.iter().chain(vpns.iter()) {
+            if swapped_out == pages {
+                break;
+            }
+            let pte = match page_table.translate(vpn) {
+                Some(pte) if pte.is_valid() => pte,
+                // Swapped out in the first round.
+                _ => continue,
+            };
+            self.clock_hand = VirtPageNum(vpn.0 + 1);
+            if pte.is_accessed() {
+                page_table.remap(vpn, pte.ppn(), pte.flags() - PTEFlags::A);
+                continue;
+            }
+            let (area, _) = self
+                .areas
+                .iter_mut()
+                .find(|(area, _)| area.vpn_range.contains(vpn))
+                .unwrap();
+            if let Err(err) = area.swap_out_one(page_table, vpn) {
+                debug!("Cannot swap out page {:?}: {}", vpn, err);
+                break;
+            }
+            swapped_out += 1;
+        }
+        swapped_out
+    }
+
+    // Releases the frames of every area; The page table itself is released
+    // when the memory set is dropped.
+    pub fn recycle_data_pages(&mut self) {
+        self.areas.clear();
+    }
+
+    // Releases the pages accessible by the user before the task exits, e.g.
+    // it's killed for the memory. Unlike `recycle_data_pages`, the pages are
+    // unmapped, since the kernel might still access the user space meanwhile;
+    // The TrapContext is kept.
+    pub fn recycle_user_pages(&mut self) {
+        let page_table = &mut self.page_table;
+        for (mut area, _) in self
+            .areas
+            .drain_filter(|(area, _)| area.map_perm.contains(MapPermission::U))
+        {
+            for vpn in area.vpn_range {
+                area.unmap_one(page_table, vpn);
+            }
+        }
+    }
+
+    // Return the number of frames taken by the pages accessible by the user,
+    // i.e. the ones released if the memory set is gone. Neither the swapped
+    // out pages nor the ones shared with another memory set are counted.
+    pub fn user_pages(&self) -> usize {
+        self.areas
+            .iter()
+            .filter(|(area, _)| area.map_perm.contains(MapPermission::U))
+            .map(|(area, _)| match &area.mapping {
+                Mapping::Framed(frames) => frames
+                    .values()
+                    .filter(|frame| Arc::strong_count(frame) == 1)
+                    .count(),
+                Mapping::Identical => 0,
+            })
+            .sum()
+    }
+
     pub fn activate(&self) {
         let satp = self.page_table.token();
         unsafe {
@@ -169,31 +588,42 @@ impl MemorySet {
         }
     }
 
+    // Return the PPN if the VPN is mapped.
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PhysPageNum> {
-        self.page_table.translate(vpn).map(|e| e.ppn())
+        self.page_table
+            .translate(vpn)
+            .filter(|e| e.is_valid())
+            .map(|e| e.ppn())
     }
 
     pub fn token(&self) -> usize {
         self.page_table.token()
     }
 
+    // Takes a new ASID in place of the current one, the memory set must be
+    // activated again if it's in use.
+    pub fn reassign_asid(&mut self) {
+        self.asid = asid_alloc();
+        self.page_table.set_asid(self.asid.0);
+    }
+
     // Trampoline is where context switch happens; The page table is changed
     // hence we need to make sure every context(either user or kernel) should
     // have the same mapping otherwise the $pc might access invalid memory
     // address.
-    fn map_trampoline(&mut self) {
+    fn map_trampoline(&mut self) -> Result<()> {
         self.page_table.map(
             VirtAddr::from(TRAMPOLINE_ADDR).into(),
             PhysAddr::from(strampoline as usize).into(),
             PTEFlags::R | PTEFlags::X,
-        );
+        )
     }
 
     // Identical mapping is required for "smooth transition" when the kernel
     // page table is activated.
     pub fn new_kernel() -> Result<Self> {
-        let mut memory_set = Self::new();
-        memory_set.map_trampoline();
+        let mut memory_set = Self::new()?;
+        memory_set.map_trampoline()?;
         println!("[kernel] Initializing kernel space");
         println!(
             "[kernel] Mapping .text section [{:#x}, {:#x})",
@@ -261,13 +691,14 @@ impl MemorySet {
         )?;
         println!(
             "[kernel] Mapping physical memory [{:#x}, {:#x})",
-            ekernel as usize, MEMORY_END as usize
+            ekernel as usize,
+            memory_end()
         );
         memory_set.push_area(
             MapArea::new(
                 VirtPageNumRange::new_from_va(
                     (ekernel as usize).into(),
-                    (MEMORY_END as usize).into(),
+                    memory_end().into(),
                 ),
                 Mapping::Identical,
                 MapPermission::R | MapPermission::W,
@@ -275,14 +706,27 @@ impl MemorySet {
             false,
             None,
         )?;
+        // The registers of the devices found in the device tree.
+        for &(base, end) in BOARD_INFO.exclusive_access().virtio_mmio.iter() {
+            println!("[kernel] Mapping virtio-mmio [{:#x}, {:#x})", base, end);
+            memory_set.push_area(
+                MapArea::new(
+                    VirtPageNumRange::new_from_va(base.into(), end.into()),
+                    Mapping::Identical,
+                    MapPermission::R | MapPermission::W,
+                ),
+                false,
+                None,
+            )?;
+        }
         Ok(memory_set)
     }
 
     /// Include sections in elf and trampoline and TrapContext and user stack,
-    /// also returns user_sp and entry point.
-    pub fn from_elf(elf_data: &[u8]) -> Result<(Self, usize, usize)> {
-        let mut memory_set = Self::new();
-        memory_set.map_trampoline();
+    /// also returns user_sp, the bottom of the heap and entry point.
+    pub fn from_elf(elf_data: &[u8]) -> Result<(Self, usize, usize, usize)> {
+        let mut memory_set = Self::new()?;
+        memory_set.map_trampoline()?;
         let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
         let elf_header = elf.header;
         assert_eq!(
@@ -319,13 +763,25 @@ impl MemorySet {
                 )?;
             }
         }
-        // map user stack with U flags
+        // The heap is empty until it grows by brk, after a guard page.
         let max_end_va: VirtAddr = max_end_vpn.into();
-        let mut user_stack_bottom: usize = max_end_va.into();
-        // guard page
-        user_stack_bottom += PAGE_SIZE;
-        let user_stack_top = user_stack_bottom + USER_STACK_SIZE;
-        memory_set.push_area(
+        let heap_bottom: usize = usize::from(max_end_va) + PAGE_SIZE;
+        memory_set.push_lazy_area(
+            MapArea::new(
+                VirtPageNumRange::new_from_va(
+                    heap_bottom.into(),
+                    heap_bottom.into(),
+                ),
+                Mapping::new_framed(),
+                MapPermission::R | MapPermission::W | MapPermission::U,
+            ),
+            false,
+        )?;
+        // map user stack with U flags
+        let user_stack_top = USER_STACK_TOP;
+        let user_stack_bottom = user_stack_top - USER_STACK_SIZE;
+        // The user stack is populated on demand.
+        memory_set.push_lazy_area(
             MapArea::new(
                 VirtPageNumRange::new_from_va(
                     user_stack_bottom.into(),
@@ -335,7 +791,6 @@ impl MemorySet {
                 MapPermission::R | MapPermission::W | MapPermission::U,
             ),
             false,
-            None,
         )?;
         // map TrapContext
         memory_set.push_area(
@@ -353,36 +808,57 @@ impl MemorySet {
         Ok((
             memory_set,
             user_stack_top,
+            heap_bottom,
             elf.header.pt2.entry_point() as usize,
         ))
     }
 }
 
 impl Mapping {
-    pub fn map(&mut self, vpn: VirtPageNum) -> PhysPageNum {
+    // Return the PPN for the VPN, a frame is allocated for it if there is
+    // none yet, which fails once the frames run out.
+    pub fn map(&mut self, vpn: VirtPageNum) -> Result<PhysPageNum> {
         match self {
-            Mapping::Identical => PhysPageNum::from(vpn.0),
+            Mapping::Identical => Ok(PhysPageNum::from(vpn.0)),
             Mapping::Framed(ref mut frames) => match frames.get(&vpn) {
-                Some(frame) => frame.0,
+                Some(frame) => Ok(frame.0),
                 None => {
-                    let frame: FrameTracker = frame_alloc().unwrap();
+                    let frame: FrameTracker = frame_alloc()?;
                     let ret = frame.0;
-                    frames.insert(vpn, frame);
-                    ret
+                    frames.insert(vpn, Arc::new(frame));
+                    Ok(ret)
                 }
             },
         }
     }
-    pub fn unmap(&mut self, vpn: VirtPageNum) {
+    // Return false if the VPN is not mapped, i.e. not populated yet.
+    pub fn unmap(&mut self, vpn: VirtPageNum) -> bool {
         match self {
-            Mapping::Identical => {}
-            Mapping::Framed(ref mut frames) => {
-                frames.remove(&vpn);
-            }
+            Mapping::Identical => true,
+            Mapping::Framed(ref mut frames) => frames.remove(&vpn).is_some(),
         }
     }
 }
 
+// Pushes the area to the end of the list, it's merged into the last one if
+// they are contiguous and alike.
+fn push_merged(
+    areas: &mut LinkedList<(MapArea, bool)>,
+    area: MapArea,
+    droppable: bool,
+) {
+    let area = match areas.back_mut() {
+        Some((last, last_droppable)) if *last_droppable == droppable => {
+            match last.merge(area) {
+                Ok(()) => return,
+                Err(area) => area,
+            }
+        }
+        _ => area,
+    };
+    areas.push_back((area, droppable));
+}
+
 impl MapArea {
     pub fn new(
         vpn_range: VirtPageNumRange,
@@ -393,6 +869,280 @@ impl MapArea {
             vpn_range,
             mapping,
             map_perm,
+            cow: false,
+            shared: false,
+            swapped: BTreeMap::new(),
+        }
+    }
+    // Marks the pages to be shared with the forked memory sets rather than
+    // copied on write, the area must be populated when it's pushed.
+    pub fn into_shared(mut self) -> Self {
+        self.shared = true;
+        self
+    }
+    // Maps a single page of this area into the page table. The frame is
+    // released again if the page table cannot map it.
+    fn map_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<PhysPageNum> {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
+        let ppn = self.mapping.map(vpn)?;
+        if let Err(err) = page_table.map(vpn, ppn, pte_flags) {
+            self.mapping.unmap(vpn);
+            return Err(err);
+        }
+        Ok(ppn)
+    }
+    // Maps all pages of this area into the page table. The identical area is
+    // mapped by the largest pages which the alignment allows, the framed one
+    // page by page since its frames are not contiguous.
+    fn map_all(&mut self, page_table: &mut PageTable) -> Result<()> {
+        match self.mapping {
+            Mapping::Identical => {
+                let pte_flags =
+                    PTEFlags::from_bits(self.map_perm.bits()).unwrap();
+                let mut vpn = self.vpn_range.get_start();
+                let end = self.vpn_range.get_end();
+                while vpn < end {
+                    let level = (0..PAGE_TABLE_LEVELS)
+                        .rev()
+                        .find(|&level| {
+                            let pages = pages_at_level(level);
+                            vpn.0 % pages == 0 && vpn.0 + pages <= end.0
+                        })
+                        .unwrap();
+                    page_table.map_huge(
+                        vpn,
+                        PhysPageNum::from(vpn.0),
+                        pte_flags,
+                        level,
+                    )?;
+                    vpn = VirtPageNum(vpn.0 + pages_at_level(level));
+                }
+            }
+            Mapping::Framed(_) => {
+                for vpn in self.vpn_range {
+                    self.map_one(page_table, vpn)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    // Maps a populated page of this area into another area as well, i.e. the
+    // area in the forked memory set. Both are mapped read-only if the page is
+    // writable, then copied on write; Unless the area is shared for good.
+    fn share_one(
+        &mut self,
+        page_table: &mut PageTable,
+        another: &mut MapArea,
+        another_page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<()> {
+        let (frame, another_frames) =
+            match (&self.mapping, &mut another.mapping) {
+                (Mapping::Framed(frames), Mapping::Framed(another_frames)) => {
+                    (frames.get(&vpn).unwrap().clone(), another_frames)
+                }
+                _ => panic!("Only the framed area can be shared"),
+            };
+        let mut pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
+        if !self.shared {
+            pte_flags -= PTEFlags::W;
+        }
+        // Nothing is changed if the other page table cannot map it.
+        another_page_table.map(vpn, frame.0, pte_flags)?;
+        another_frames.insert(vpn, frame.clone());
+        if !self.shared {
+            self.cow = true;
+            another.cow = true;
+        }
+        page_table.remap(vpn, frame.0, pte_flags);
+        Ok(())
+    }
+    // Makes a shared page writable for this area. The page is copied unless
+    // nobody else refers to it anymore.
+    fn copy_on_write(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<()> {
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits()).unwrap();
+        let frame = match &mut self.mapping {
+            Mapping::Framed(frames) => frames.get_mut(&vpn).unwrap(),
+            Mapping::Identical => panic!("The identical area is never shared"),
+        };
+        if Arc::strong_count(frame) > 1 {
+            let new_frame = frame_alloc()?;
+            new_frame
+                .0
+                .get_bytes_array()
+                .copy_from_slice(frame.0.get_bytes_array());
+            *frame = Arc::new(new_frame);
+        }
+        page_table.remap(vpn, frame.0, pte_flags);
+        Ok(())
+    }
+    // Return the populated pages which might be swapped out, i.e. the user
+    // pages owned by this area alone. The pages of MAP_SHARED are never
+    // swapped out, nor the ones shared by copy on write.
+    fn swappable_pages(&self) -> Vec<VirtPageNum> {
+        match &self.mapping {
+            Mapping::Framed(frames)
+                if self.map_perm.contains(MapPermission::U) && !self.shared =>
+            {
+                frames
+                    .iter()
+                    .filter(|(_, frame)| Arc::strong_count(frame) == 1)
+                    .map(|(&vpn, _)| vpn)
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+    // Writes a populated page of this area to the swap area, then unmaps it
+    // and releases its frame.
+    fn swap_out_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<()> {
+        let slot = SwapSlot::alloc()?;
+        let frame = match &mut self.mapping {
+            Mapping::Framed(frames) => frames.remove(&vpn).unwrap(),
+            Mapping::Identical => panic!("The identical area is never swapped"),
+        };
+        slot.write(frame.0);
+        page_table.unmap(vpn);
+        self.swapped.insert(vpn, slot);
+        Ok(())
+    }
+    // Reads a swapped out page of this area into a new frame and maps it, the
+    // slot is released unless there is no frame for it.
+    fn swap_in_one(
+        &mut self,
+        page_table: &mut PageTable,
+        vpn: VirtPageNum,
+    ) -> Result<()> {
+        let ppn = self.map_one(page_table, vpn)?;
+        let slot = self.swapped.remove(&vpn).unwrap();
+        slot.read(ppn);
+        Ok(())
+    }
+    // Unmaps a single page of this area from the page table if it's mapped,
+    // or releases its slot if it's swapped out.
+    fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
+        self.swapped.remove(&vpn);
+        if self.mapping.unmap(vpn) {
+            page_table.unmap(vpn);
+        }
+    }
+    // Splits off the pages starting from the given VPN into a new area.
+    fn split_off(&mut self, at: VirtPageNum) -> MapArea {
+        let mapping = match &mut self.mapping {
+            Mapping::Identical => Mapping::Identical,
+            Mapping::Framed(frames) => Mapping::Framed(frames.split_off(&at)),
+        };
+        let another = MapArea {
+            vpn_range: VirtPageNumRange::new(at, self.vpn_range.get_end()),
+            mapping,
+            map_perm: self.map_perm,
+            cow: self.cow,
+            shared: self.shared,
+            swapped: self.swapped.split_off(&at),
+        };
+        self.vpn_range = VirtPageNumRange::new(self.vpn_range.get_start(), at);
+        another
+    }
+    // Splits the area into the parts before, inside and after the given range.
+    fn split(
+        self,
+        vpn_range: VirtPageNumRange,
+    ) -> (Option<MapArea>, Option<MapArea>, Option<MapArea>) {
+        let start = max(self.vpn_range.get_start(), vpn_range.get_start());
+        let end = min(self.vpn_range.get_end(), vpn_range.get_end());
+        if start >= end {
+            return (Some(self), None, None);
+        }
+        let mut inside = self;
+        let head = if start > inside.vpn_range.get_start() {
+            let rest = inside.split_off(start);
+            Some(core::mem::replace(&mut inside, rest))
+        } else {
+            None
+        };
+        let tail = if end < inside.vpn_range.get_end() {
+            Some(inside.split_off(end))
+        } else {
+            None
+        };
+        (head, Some(inside), tail)
+    }
+    // Appends another area which starts right at the end of this one, return
+    // it back if they cannot be merged.
+    fn merge(&mut self, another: MapArea) -> core::result::Result<(), MapArea> {
+        let mergeable = self.vpn_range.get_end()
+            == another.vpn_range.get_start()
+            && self.map_perm == another.map_perm
+            && self.shared == another.shared
+            && matches!(
+                (&self.mapping, &another.mapping),
+                (Mapping::Framed(_), Mapping::Framed(_))
+                    | (Mapping::Identical, Mapping::Identical)
+            );
+        if !mergeable {
+            return Err(another);
+        }
+        if let (Mapping::Framed(frames), Mapping::Framed(mut another_frames)) =
+            (&mut self.mapping, another.mapping)
+        {
+            frames.append(&mut another_frames);
+        }
+        let mut another_swapped = another.swapped;
+        self.swapped.append(&mut another_swapped);
+        self.vpn_range = VirtPageNumRange::new(
+            self.vpn_range.get_start(),
+            another.vpn_range.get_end(),
+        );
+        self.cow |= another.cow;
+        Ok(())
+    }
+    // Changes the permission of this area and updates the populated pages.
+    fn protect(&mut self, page_table: &mut PageTable, map_perm: MapPermission) {
+        self.map_perm = map_perm;
+        let pte_flags = PTEFlags::from_bits(map_perm.bits()).unwrap();
+        for vpn in self.vpn_range {
+            match &self.mapping {
+                Mapping::Identical => {
+                    page_table.remap(vpn, PhysPageNum::from(vpn.0), pte_flags)
+                }
+                Mapping::Framed(frames) => match frames.get(&vpn) {
+                    // The shared page stays read-only until copy on write.
+                    Some(frame)
+                        if !self.shared && Arc::strong_count(frame) > 1 =>
+                    {
+                        page_table.remap(vpn, frame.0, pte_flags - PTEFlags::W)
+                    }
+                    Some(frame) => page_table.remap(vpn, frame.0, pte_flags),
+                    None => {}
+                },
+            }
+        }
+    }
+    // Creates an area with the same range and permission as another one,
+    // no frame is allocated for it yet.
+    pub fn from_another(another: &MapArea) -> Self {
+        Self {
+            vpn_range: another.vpn_range,
+            mapping: match another.mapping {
+                Mapping::Identical => Mapping::Identical,
+                Mapping::Framed(_) => Mapping::new_framed(),
+            },
+            map_perm: another.map_perm,
+            cow: false,
+            shared: another.shared,
+            swapped: BTreeMap::new(),
         }
     }
 }
@@ -1,15 +1,22 @@
 mod address;
+mod asid;
 mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod swap;
+mod user_ptr;
 
 use crate::sync::UPSafeCell;
 pub use address::*;
 use alloc::sync::Arc;
+pub use frame_allocator::{frame_alloc_contiguous, FrameTracker};
+pub use heap_allocator::heap_stats;
 use lazy_static::*;
 pub use memory_set::{MapArea, MapPermission, Mapping, MemorySet};
-pub use page_table::{translated_byte_buffer, translated_mut_byte_buffer};
+pub use page_table::virt_to_phys;
+pub use swap::{balance_frames, register_memory_set};
+pub use user_ptr::{check_user_range, read_cstr, UserPtr, UserSlice};
 
 lazy_static! {
     pub static ref KERNEL_SPACE: Arc<UPSafeCell<MemorySet>> =
@@ -21,11 +28,28 @@ lazy_static! {
         });
 }
 
-pub fn init() {
+// The heap is ready before the others since parsing the device tree needs it.
+pub fn init_heap() {
     heap_allocator::init_heap();
-    // The frame allocator depends on the heap allocator.
+}
+
+// The frame allocator depends on the heap allocator and the board information;
+// The ASIDs are probed once the kernel space is active, which then takes one
+// of them in place of the shared ASID 0; The swap area depends on the kernel
+// space, where the devices are mapped.
+pub fn init() {
     frame_allocator::init_frame_allocator();
+    #[cfg(debug_assertions)]
+    frame_allocator::frame_allocator_test();
+    #[cfg(debug_assertions)]
+    heap_allocator::heap_grow_test();
     KERNEL_SPACE.exclusive_access().activate();
+    asid::init();
+    let mut kernel_space = KERNEL_SPACE.exclusive_access();
+    kernel_space.reassign_asid();
+    kernel_space.activate();
+    drop(kernel_space);
     #[cfg(debug_assertions)]
     memory_set::remap_kernel_test();
+    swap::init();
 }
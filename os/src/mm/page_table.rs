@@ -1,19 +1,26 @@
 // This file includes PageTable and PageTableEntry. Here we use SV39 scheme
 // which includes 12 bits of page size and 3-level page table, where the page
-// directory in each level takes 9 bits.
+// directory in each level takes 9 bits; Or SV48 scheme with the feature
+// `sv48`, which has 4-level page table instead.
 //
 // Check docs/pics/page_table.svg for details.
 use super::address::*;
+use super::asid::{flush_tlb, SATP_ASID_MASK, SATP_ASID_SHIFT};
 use super::frame_allocator::*;
-use crate::utils::StepByOne;
+use crate::config::VA_BITS_PER_LEVEL;
+use crate::error::Result;
 use alloc::vec::*;
 use bitflags::*;
 use core::fmt::{self, Debug, Formatter};
+use riscv::register::satp;
 
 use super::frame_allocator::FrameTracker;
 
-const SATP_MODE_SV39: usize = 8usize << 60;
-const PTE_WIDTH_SV39: usize = 54;
+#[cfg(not(feature = "sv48"))]
+const SATP_MODE: usize = 8usize << 60;
+#[cfg(feature = "sv48")]
+const SATP_MODE: usize = 9usize << 60;
+const PTE_WIDTH: usize = 54;
 const PTE_PAGE_NUM_START_BIT: usize = 10;
 // The number of PTE in page table each level.
 pub const PT_SIZE: usize = 1 << 9;
@@ -45,15 +52,37 @@ bitflags! {
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub struct PageTableEntry(usize);
-// The page table has 3-level page directory; The page directory in each level
-//  has 512 (2^9) page table entries and each entry take 8 bytes.
+// The page table has 3-level(or 4-level for SV48) page directory; The page
+// directory in each level has 512 (2^9) page table entries and each entry take
+// 8 bytes.
+// A valid PTE with any of R/W/X is a leaf, which could be at level 1 or 2 as
+// well as level 0, mapping a 2 MiB megapage or a 1 GiB gigapage respectively;
+// SV48 also allows a 512 GiB terapage at level 3.
 pub struct PageTable {
     // The physical page num of the root level page directory.
     root_ppn: PhysPageNum,
+    // The ASID of the address space, which tags its TLB entries.
+    asid: usize,
     // Keeps track of each frame, which is allocated from FrameAllocator.
     frames: Vec<FrameTracker>,
 }
 
+// The number of 4 KiB pages covered by a leaf PTE at the given level.
+pub fn pages_at_level(level: usize) -> usize {
+    1 << (level * VA_BITS_PER_LEVEL)
+}
+
+// Translates a virtual address in the current address space, e.g. on the
+// kernel stack, into the physical one. The page table is found by satp rather
+// than the memory set, hence it works while the memory set is borrowed.
+pub fn virt_to_phys(va: usize) -> Option<usize> {
+    let va = VirtAddr::from(va);
+    PageTable::from_token(satp::read().bits())
+        .translate(va.floor())
+        .filter(|pte| pte.is_valid())
+        .map(|pte| PhysAddr::from(pte.ppn()).0 + va.page_offset())
+}
+
 impl Debug for PageTableEntry {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.write_fmt(format_args!("PTE:{:?}|{:?}", self.ppn(), self.flags()))
@@ -68,7 +97,7 @@ impl PageTableEntry {
         Self(0)
     }
     pub fn ppn(&self) -> PhysPageNum {
-        ((self.0 & ((1usize << PTE_WIDTH_SV39) - 1)) >> PTE_PAGE_NUM_START_BIT)
+        ((self.0 & ((1usize << PTE_WIDTH) - 1)) >> PTE_PAGE_NUM_START_BIT)
             .into()
     }
     pub fn flags(&self) -> PTEFlags {
@@ -88,35 +117,103 @@ impl PageTableEntry {
     pub fn executable(&self) -> bool {
         (self.flags() & PTEFlags::X) != PTEFlags::empty()
     }
+    pub fn is_user(&self) -> bool {
+        (self.flags() & PTEFlags::U) != PTEFlags::empty()
+    }
+    // The hardware sets A once the page is accessed, which is cleared by the
+    // page replacement to tell the pages in use.
+    pub fn is_accessed(&self) -> bool {
+        (self.flags() & PTEFlags::A) != PTEFlags::empty()
+    }
+    pub fn is_leaf(&self) -> bool {
+        self.is_valid()
+            && (self.flags() & (PTEFlags::R | PTEFlags::W | PTEFlags::X))
+                != PTEFlags::empty()
+    }
 }
 
 impl PageTable {
-    pub fn new() -> Self {
-        let root_page_directory = frame_alloc().unwrap();
-        PageTable {
+    pub fn new(asid: usize) -> Result<Self> {
+        let root_page_directory = frame_alloc()?;
+        Ok(PageTable {
             root_ppn: root_page_directory.0,
+            asid,
             frames: vec![root_page_directory],
-        }
+        })
     }
     pub fn from_token(satp: usize) -> Self {
         Self {
             root_ppn: PhysPageNum::from(satp),
+            asid: (satp >> SATP_ASID_SHIFT) & SATP_ASID_MASK,
             frames: Vec::new(),
         }
     }
     pub fn token(&self) -> usize {
-        self.root_ppn.0 | SATP_MODE_SV39
+        self.root_ppn.0 | (self.asid << SATP_ASID_SHIFT) | SATP_MODE
     }
+    pub fn set_asid(&mut self, asid: usize) {
+        self.asid = asid;
+    }
+    // Returns the PTE of the 4 KiB page given a VPN. If it's in a huge page,
+    // the PPN of the returned PTE points to the 4 KiB page within it.
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
-        self.find_pte(vpn).map(|pte| pte.clone())
+        self.find_pte(vpn).map(|(pte, level)| {
+            let offset = vpn.0 & (pages_at_level(level) - 1);
+            PageTableEntry::new(pte.ppn() + offset, pte.flags())
+        })
     }
 
     // Populates PTEs in this page table given the mapping
     // intention(VPN, PPN & flags).
-    pub fn map(&mut self, vpn: VirtPageNum, ppn: PhysPageNum, flags: PTEFlags) {
-        let pte = self.find_mut_pte(vpn);
+    pub fn map(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+    ) -> Result<()> {
+        self.map_huge(vpn, ppn, flags, 0)
+    }
+
+    // Populates a leaf PTE at the given level, which maps
+    // pages_at_level(level) pages; Both of the VPN and PPN must be aligned to
+    // it. It fails if there is no frame for the missing page directories.
+    pub fn map_huge(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+        level: usize,
+    ) -> Result<()> {
+        let pages = pages_at_level(level);
+        assert!(
+            vpn.0 % pages == 0 && ppn.0 % pages == 0,
+            "vpn {:#x} or ppn {:#x} is not aligned to level {}",
+            vpn.0,
+            ppn.0,
+            level
+        );
+        let pte = self.find_mut_pte_at(vpn, level)?;
         assert!(!pte.is_valid(), "vpn {:#x} is mapped before mapping", vpn.0);
         *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.flush(vpn);
+        Ok(())
+    }
+
+    // Replaces the PPN and flags of a mapped PTE given the VPN.
+    pub fn remap(
+        &mut self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PTEFlags,
+    ) {
+        let pte = self.find_mut_pte(vpn);
+        assert!(
+            pte.is_valid(),
+            "vpn {:#x} is invalid before remapping",
+            vpn.0
+        );
+        *pte = PageTableEntry::new(ppn, flags | PTEFlags::V);
+        self.flush(vpn);
     }
 
     // Removes PTEs in this page table given the VPN.
@@ -128,99 +225,74 @@ impl PageTable {
             vpn.0
         );
         *pte = PageTableEntry::empty();
+        self.flush(vpn);
     }
 
-    // Returns the mutable PTE given a VPN.
-    // When there is a missing of page directory, automatically allocates one
-    // physical page from frame allocator.
+    // Flushes the TLB entry of the VPN in this address space only, since the
+    // others are tagged by different ASIDs.
+    fn flush(&self, vpn: VirtPageNum) {
+        flush_tlb(self.asid, Some(VirtAddr::from(vpn).0));
+    }
+
+    // Returns the mutable PTE of a mapped 4 KiB page given a VPN, whose page
+    // directories exist already.
     fn find_mut_pte(&mut self, vpn: VirtPageNum) -> &mut PageTableEntry {
+        match self.find_mut_pte_at(vpn, 0) {
+            Ok(pte) => pte,
+            Err(err) => panic!("vpn {:#x} is not mapped: {}", vpn.0, err),
+        }
+    }
+
+    // Returns the mutable PTE at the given level given a VPN.
+    // When there is a missing of page directory, automatically allocates one
+    // physical page from frame allocator, which fails if there is none.
+    fn find_mut_pte_at(
+        &mut self,
+        vpn: VirtPageNum,
+        level: usize,
+    ) -> Result<&mut PageTableEntry> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
         let mut result: Option<&mut PageTableEntry> = None;
         for (i, vpn_id) in idxs.iter().enumerate().rev() {
             let pte = &mut ppn.get_pte_array()[*vpn_id];
-            if i == 0 {
+            if i == level {
                 result = Some(pte);
                 break;
             }
+            assert!(
+                !pte.is_leaf(),
+                "vpn {:#x} is in a huge page at level {}",
+                vpn.0,
+                i
+            );
             if !pte.is_valid() {
-                let page_directory = frame_alloc().unwrap();
+                let page_directory = frame_alloc()?;
                 *pte = PageTableEntry::new(page_directory.0, PTEFlags::V);
                 assert!(pte.is_valid());
                 self.frames.push(page_directory);
             }
             ppn = pte.ppn();
         }
-        result.unwrap()
+        Ok(result.unwrap())
     }
 
-    // Returns the PTE given a VPN.
+    // Returns the PTE and its level given a VPN, the walk stops at the first
+    // leaf.
     // When there is a missing of page directory, return none.
-    fn find_pte(&self, vpn: VirtPageNum) -> Option<&PageTableEntry> {
+    fn find_pte(&self, vpn: VirtPageNum) -> Option<(&PageTableEntry, usize)> {
         let idxs = vpn.indexes();
         let mut ppn = self.root_ppn;
-        let mut result: Option<&PageTableEntry> = None;
         for (i, vpn_id) in idxs.iter().enumerate().rev() {
-            let pte = &mut ppn.get_pte_array()[*vpn_id];
-            if i == 0 {
-                result = Some(pte);
-                break;
+            let pte = &ppn.get_pte_array()[*vpn_id];
+            if i == 0 || pte.is_leaf() {
+                return Some((pte, i));
             }
             if !pte.is_valid() {
                 return None;
             }
             ppn = pte.ppn();
         }
-        result
-    }
-}
-
-// Returns a list of memory slice in physical address given the root address
-// of page table.
-pub fn translated_byte_buffer(
-    token: usize,
-    ptr: *const u8,
-    len: usize,
-) -> Vec<&'static [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start_va = VirtAddr::from(ptr as usize);
-    let end_va = VirtAddr::from(ptr as usize + len);
-    let mut v = Vec::new();
-    while start_va < end_va {
-        let mut start_vpn = start_va.floor();
-        let ppn = page_table.translate(start_vpn).unwrap().ppn();
-        start_vpn.step();
-        let cur_end_va: VirtAddr = end_va.min(start_vpn.into());
-        v.push(
-            &ppn.get_bytes_array()
-                [start_va.page_offset()..cur_end_va.page_offset()],
-        );
-        start_va = cur_end_va;
-    }
-    v
-}
-
-// Returns a list of mutable memory slice in physical address given the
-// root address of page table.
-pub fn translated_mut_byte_buffer(
-    token: usize,
-    ptr: *const u8,
-    len: usize,
-) -> Vec<&'static mut [u8]> {
-    let page_table = PageTable::from_token(token);
-    let mut start_va = VirtAddr::from(ptr as usize);
-    let end_va = VirtAddr::from(ptr as usize + len);
-    let mut v = Vec::new();
-    while start_va < end_va {
-        let mut start_vpn = start_va.floor();
-        let ppn = page_table.translate(start_vpn).unwrap().ppn();
-        start_vpn.step();
-        let cur_end_va: VirtAddr = end_va.min(start_vpn.into());
-        v.push(
-            &mut ppn.get_bytes_array()
-                [start_va.page_offset()..cur_end_va.page_offset()],
-        );
-        start_va = cur_end_va;
+        None
     }
-    v
 }
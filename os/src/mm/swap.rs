@@ -0,0 +1,202 @@
+// The swap area on the block device, where the user pages are evicted to under
+// memory pressure; They're faulted back in by `MemorySet::handle_page_fault`.
+//
+// The pages are replaced by the clock algorithm: The hand goes around the user
+// memory sets registered here, and around the pages within each of them. A
+// page with A set gets a second chance, i.e. A is cleared and the hand moves
+// on; The first page found with A clear is evicted. The page is written out
+// regardless of D, since its slot is released once it's swapped in.
+use super::address::PhysPageNum;
+use super::frame_allocator::free_frames;
+use super::MemorySet;
+use crate::config::PAGE_SIZE;
+use crate::drivers::block::{BlockDevice, BLOCK_DEVICE, BLOCK_SIZE};
+use crate::error::{KernelError, Result};
+use crate::sync::UPSafeCell;
+use alloc::string::ToString;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use lazy_static::*;
+
+// The pages are swapped out ahead of time once the free frames are fewer than
+// the low watermark, until there are as many as the high one.
+const FREE_FRAMES_LOW: usize = 64;
+const FREE_FRAMES_HIGH: usize = 128;
+// The memory set under the hand evicts at most this many pages before the
+// hand moves on to the next one.
+const SWAP_OUT_BATCH: usize = 16;
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE / BLOCK_SIZE;
+
+struct SwapSlotAllocator {
+    // The number of slots in the swap area, 0 if there is no block device.
+    max: usize,
+    // The smallest slot which has never been allocated.
+    current: usize,
+    // A list contains recycled slots.
+    recycled: Vec<usize>,
+}
+
+// A slot of the swap area holding a page, it's freed once dropped.
+#[derive(Debug)]
+pub struct SwapSlot(usize);
+
+struct Clock {
+    // The user memory sets whose pages might be swapped out, the dropped
+    // ones are removed lazily once another one is registered.
+    memory_sets: Vec<Weak<UPSafeCell<MemorySet>>>,
+    // The index of the memory set under the hand.
+    hand: usize,
+}
+
+lazy_static! {
+    static ref SWAP_SLOT_ALLOCATOR: UPSafeCell<SwapSlotAllocator> = unsafe {
+        UPSafeCell::new(SwapSlotAllocator {
+            max: 0,
+            current: 0,
+            recycled: Vec::new(),
+        })
+    };
+    static ref CLOCK: UPSafeCell<Clock> = unsafe {
+        UPSafeCell::new(Clock {
+            memory_sets: Vec::new(),
+            hand: 0,
+        })
+    };
+}
+
+// The whole block device is taken as the swap area, if there is one.
+pub fn init() {
+    let max = BLOCK_DEVICE
+        .as_ref()
+        .map_or(0, |device| device.num_blocks() / BLOCKS_PER_PAGE);
+    SWAP_SLOT_ALLOCATOR.exclusive_access().max = max;
+    if max == 0 {
+        println!("[kernel] No swap area, the user pages stay in memory");
+    } else {
+        println!("[kernel] Swap area of {} pages", max);
+    }
+}
+
+fn swap_enabled() -> bool {
+    SWAP_SLOT_ALLOCATOR.exclusive_access().max > 0
+}
+
+// Makes the pages of the user memory set candidates for swapping out.
+pub fn register_memory_set(memory_set: &Arc<UPSafeCell<MemorySet>>) {
+    let mut clock = CLOCK.exclusive_access();
+    clock
+        .memory_sets
+        .retain(|memory_set| memory_set.strong_count() > 0);
+    clock.memory_sets.push(Arc::downgrade(memory_set));
+}
+
+// Swaps out at most the given number of pages, return how many are swapped
+// out. The memory sets in use, e.g. the one allocating the frames, are skipped.
+pub fn reclaim_frames(pages: usize) -> usize {
+    if !swap_enabled() {
+        return 0;
+    }
+    // It's reclaiming already if the clock is in use.
+    let mut clock = match CLOCK.try_exclusive_access() {
+        Some(clock) => clock,
+        None => return 0,
+    };
+    let mut swapped_out = 0;
+    // Gives up once the hand goes around without swapping out anything.
+    let mut idle = 0;
+    while swapped_out < pages && idle < clock.memory_sets.len() {
+        let hand = clock.hand % clock.memory_sets.len();
+        clock.hand = hand + 1;
+        let batch = SWAP_OUT_BATCH.min(pages - swapped_out);
+        let count = clock.memory_sets[hand].upgrade().map_or(0, |memory_set| {
+            memory_set
+                .try_exclusive_access()
+                .map_or(0, |mut memory_set| memory_set.swap_out(batch))
+        });
+        if count == 0 {
+            idle += 1;
+        } else {
+            idle = 0;
+            swapped_out += count;
+        }
+    }
+    debug!("Swapped out {} pages to reclaim {}", swapped_out, pages);
+    swapped_out
+}
+
+// Swaps out pages ahead of time once the free frames run low. It's called at
+// the entry of trap, where no memory set is borrowed.
+pub fn balance_frames() {
+    let free = free_frames();
+    if free < FREE_FRAMES_LOW {
+        reclaim_frames(FREE_FRAMES_HIGH - free);
+    }
+}
+
+impl SwapSlot {
+    pub fn alloc() -> Result<Self> {
+        SWAP_SLOT_ALLOCATOR.exclusive_access().alloc()
+    }
+
+    // Writes the page into this slot.
+    pub fn write(&self, ppn: PhysPageNum) {
+        let device = BLOCK_DEVICE.as_ref().unwrap();
+        for (i, block) in ppn.get_bytes_array().chunks(BLOCK_SIZE).enumerate() {
+            device.write_block(self.0 * BLOCKS_PER_PAGE + i, block);
+        }
+    }
+
+    // Reads this slot into the page.
+    pub fn read(&self, ppn: PhysPageNum) {
+        let device = BLOCK_DEVICE.as_ref().unwrap();
+        for (i, block) in
+            ppn.get_bytes_array().chunks_mut(BLOCK_SIZE).enumerate()
+        {
+            device.read_block(self.0 * BLOCKS_PER_PAGE + i, block);
+        }
+    }
+
+    // Copies this slot into a new one block by block, which takes no frame.
+    pub fn duplicate(&self) -> Result<Self> {
+        let device = BLOCK_DEVICE.as_ref().unwrap();
+        let slot = Self::alloc()?;
+        let mut block = [0u8; BLOCK_SIZE];
+        for i in 0..BLOCKS_PER_PAGE {
+            device.read_block(self.0 * BLOCKS_PER_PAGE + i, &mut block);
+            device.write_block(slot.0 * BLOCKS_PER_PAGE + i, &block);
+        }
+        Ok(slot)
+    }
+}
+
+impl Drop for SwapSlot {
+    fn drop(&mut self) {
+        SWAP_SLOT_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+impl SwapSlotAllocator {
+    fn alloc(&mut self) -> Result<SwapSlot> {
+        if let Some(slot) = self.recycled.pop() {
+            Ok(SwapSlot(slot))
+        } else if self.current < self.max {
+            self.current += 1;
+            Ok(SwapSlot(self.current - 1))
+        } else {
+            Err(KernelError::OutOfMemory(
+                "The swap area is full".to_string(),
+            ))
+        }
+    }
+    // The double free is only checked in debug mode, since there might be
+    // thousands of recycled slots.
+    fn dealloc(&mut self, slot: usize) {
+        assert!(slot < self.current, "Swap slot {} is out of range", slot);
+        debug_assert!(
+            !self.recycled.contains(&slot),
+            "Swap slot {} has not been allocated!",
+            slot
+        );
+        self.recycled.push(slot);
+    }
+}
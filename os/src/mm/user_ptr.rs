@@ -0,0 +1,215 @@
+// This file includes the accessors of user space for syscalls. A pointer from
+// user space is never trusted: Each page it touches must be mapped with U and
+// readable or writable accordingly in the page table of the current task,
+// otherwise the syscall fails with EFAULT.
+use super::address::*;
+use super::page_table::PageTable;
+use super::MapPermission;
+use crate::config::USER_STACK_TOP;
+use crate::error::{KernelError, Result};
+use crate::task::current_user_memory_set;
+use crate::utils::StepByOne;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::size_of;
+
+// Returns the PPN of a user page for the kernel to access. The page might be
+// reserved by a lazy area of the current task but not populated yet, or shared
+// by copy on write when the kernel writes to it; In which case the page fault
+// is handled here as if the user touched it, hence the token must belong to
+// the current task and its memory set must not be borrowed by the caller.
+fn translate_user_page(
+    page_table: &PageTable,
+    vpn: VirtPageNum,
+    access: MapPermission,
+) -> Result<PhysPageNum> {
+    let accessible = |page_table: &PageTable| {
+        page_table
+            .translate(vpn)
+            .filter(|pte| {
+                pte.is_valid()
+                    && pte.is_user()
+                    && match access {
+                        MapPermission::W => pte.writable(),
+                        _ => pte.readable(),
+                    }
+            })
+            .map(|pte| pte.ppn())
+    };
+    if let Some(ppn) = accessible(page_table) {
+        return Ok(ppn);
+    }
+    // The page fault handler refuses the pages that the user cannot access
+    // either, which is reported as a bad address anyway; Unless there is no
+    // memory to populate the page.
+    let result = current_user_memory_set()
+        .exclusive_access()
+        .handle_page_fault(vpn, access);
+    if let Err(err @ KernelError::OutOfMemory(_)) = result {
+        return Err(err);
+    }
+    accessible(page_table)
+        .ok_or_else(|| KernelError::BadAddress(VirtAddr::from(vpn).0))
+}
+
+// The user space is the lower half of the address space, a range beyond it
+// would wrap around when it's converted into VirtAddr. Return the end of the
+// range.
+pub fn check_user_range(start: usize, len: usize) -> Result<usize> {
+    match start.checked_add(len) {
+        Some(end) if end <= USER_STACK_TOP => Ok(end),
+        _ => Err(KernelError::BadAddress(start)),
+    }
+}
+
+// A byte buffer in user space given the root address of page table.
+pub struct UserSlice {
+    token: usize,
+    start: usize,
+    len: usize,
+}
+
+impl UserSlice {
+    pub fn new(token: usize, ptr: *const u8, len: usize) -> Self {
+        Self {
+            token,
+            start: ptr as usize,
+            len,
+        }
+    }
+
+    // Returns a list of memory slice in physical address for the given
+    // access, since the buffer might map to non-contiguous frames.
+    fn translate(
+        &self,
+        access: MapPermission,
+    ) -> Result<Vec<&'static mut [u8]>> {
+        let end = check_user_range(self.start, self.len)?;
+        let page_table = PageTable::from_token(self.token);
+        let mut start_va = VirtAddr::from(self.start);
+        let end_va = VirtAddr::from(end);
+        let mut v = Vec::new();
+        while start_va < end_va {
+            let mut start_vpn = start_va.floor();
+            let ppn = translate_user_page(&page_table, start_vpn, access)?;
+            start_vpn.step();
+            let cur_end_va: VirtAddr = end_va.min(start_vpn.into());
+            // The offset of the end is 0 if the slice reaches the end of the
+            // page.
+            if cur_end_va.page_offset() == 0 {
+                v.push(&mut ppn.get_bytes_array()[start_va.page_offset()..]);
+            } else {
+                v.push(
+                    &mut ppn.get_bytes_array()
+                        [start_va.page_offset()..cur_end_va.page_offset()],
+                );
+            }
+            start_va = cur_end_va;
+        }
+        Ok(v)
+    }
+
+    // Returns the readable slices in physical address.
+    pub fn buffers(&self) -> Result<Vec<&'static [u8]>> {
+        Ok(self
+            .translate(MapPermission::R)?
+            .into_iter()
+            .map(|buf| &*buf)
+            .collect())
+    }
+
+    // Returns the writable slices in physical address.
+    pub fn buffers_mut(&self) -> Result<Vec<&'static mut [u8]>> {
+        self.translate(MapPermission::W)
+    }
+
+    // Copies the user buffer into the kernel one, which must be as long as the
+    // user buffer.
+    pub fn copy_from_user(&self, dst: &mut [u8]) -> Result<()> {
+        assert_eq!(dst.len(), self.len);
+        let mut offset = 0;
+        for buf in self.buffers()? {
+            dst[offset..offset + buf.len()].copy_from_slice(buf);
+            offset += buf.len();
+        }
+        Ok(())
+    }
+
+    // Copies the kernel buffer into the user one, which must be as long as the
+    // user buffer.
+    pub fn copy_to_user(&self, src: &[u8]) -> Result<()> {
+        assert_eq!(src.len(), self.len);
+        let mut offset = 0;
+        for buf in self.buffers_mut()? {
+            buf.copy_from_slice(&src[offset..offset + buf.len()]);
+            offset += buf.len();
+        }
+        Ok(())
+    }
+}
+
+// A pointer to an object in user space given the root address of page table.
+// The object is copied byte by byte, hence it might be unaligned or cross
+// pages in user space.
+pub struct UserPtr<T> {
+    slice: UserSlice,
+    _marker: PhantomData<T>,
+}
+
+impl<T> UserPtr<T> {
+    pub fn new(token: usize, ptr: *mut T) -> Self {
+        Self {
+            slice: UserSlice::new(token, ptr as *const u8, size_of::<T>()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn copy_to_user(&self, value: &T) -> Result<()> {
+        let src = unsafe {
+            core::slice::from_raw_parts(
+                value as *const T as *const u8,
+                size_of::<T>(),
+            )
+        };
+        self.slice.copy_to_user(src)
+    }
+}
+
+impl<T: Copy> UserPtr<T> {
+    // The object must be valid for any bit pattern, e.g. integers or structs
+    // of them.
+    pub fn copy_from_user(&self) -> Result<T> {
+        let mut value = core::mem::MaybeUninit::<T>::uninit();
+        let dst = unsafe {
+            core::slice::from_raw_parts_mut(
+                value.as_mut_ptr() as *mut u8,
+                size_of::<T>(),
+            )
+        };
+        self.slice.copy_from_user(dst)?;
+        Ok(unsafe { value.assume_init() })
+    }
+}
+
+// Returns a string copied from user space given the root address of page
+// table and a pointer to a null-terminated string.
+pub fn read_cstr(token: usize, ptr: *const u8) -> Result<String> {
+    let page_table = PageTable::from_token(token);
+    let mut string = String::new();
+    let mut va = ptr as usize;
+    loop {
+        check_user_range(va, 1)?;
+        let cur_va = VirtAddr::from(va);
+        let ppn =
+            translate_user_page(&page_table, cur_va.floor(), MapPermission::R)?;
+        // Reads the rest of the page at once.
+        for &ch in &ppn.get_bytes_array()[cur_va.page_offset()..] {
+            if ch == 0 {
+                return Ok(string);
+            }
+            string.push(ch as char);
+            va += 1;
+        }
+    }
+}
@@ -16,6 +16,22 @@ fn sbi_call(which: usize, arg0: usize, arg1: usize, arg2: usize) -> usize {
     }
     ret
 }
+// Calls into the SBI extensions since v0.2, where a7 is the extension id and
+// a6 is the function id. Returns the error code in a0.
+#[inline(always)]
+fn sbi_call_ext(eid: usize, fid: usize, arg0: usize, arg1: usize) -> isize {
+    let mut error;
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("x10") arg0 => error,
+            inlateout("x11") arg1 => _,
+            in("x16") fid,
+            in("x17") eid,
+        );
+    }
+    error
+}
 const SBI_SET_TIMER: usize = 0;
 const SBI_CONSOLE_PUTCHAR: usize = 1;
 const SBI_CONSOLE_GETCHAR: usize = 2;
@@ -26,11 +42,36 @@ const SBI_REMOTE_SFENCE_VMA: usize = 6;
 const SBI_REMOTE_SFENCE_VMA_ASID: usize = 7;
 const SBI_SHUTDOWN: usize = 8;
 
+// System Reset extension, "SRST" in ASCII.
+const SBI_EXT_SRST: usize = 0x5352_5354;
+const SBI_SRST_SYSTEM_RESET: usize = 0;
+const SBI_SRST_TYPE_SHUTDOWN: usize = 0;
+const SBI_SRST_REASON_NONE: usize = 0;
+const SBI_SRST_REASON_FAILURE: usize = 1;
+
 pub fn console_putchar(c: usize) {
     sbi_call(SBI_CONSOLE_PUTCHAR, c, 0, 0);
 }
 
-pub fn shutdown() -> ! {
+pub fn console_getchar() -> usize {
+    sbi_call(SBI_CONSOLE_GETCHAR, 0, 0, 0)
+}
+
+// Powers off the machine. The failure is reported as the reset reason, so that
+// QEMU exits with a non-zero status.
+pub fn shutdown(failure: bool) -> ! {
+    let reason = if failure {
+        SBI_SRST_REASON_FAILURE
+    } else {
+        SBI_SRST_REASON_NONE
+    };
+    sbi_call_ext(
+        SBI_EXT_SRST,
+        SBI_SRST_SYSTEM_RESET,
+        SBI_SRST_TYPE_SHUTDOWN,
+        reason,
+    );
+    // Falls back to the legacy one if the SRST extension is not supported.
     sbi_call(SBI_SHUTDOWN, 0, 0, 0);
     panic!("It should shutdown!");
 }
@@ -1,27 +1,131 @@
-use crate::mm::translated_byte_buffer;
-use crate::task::{current_idx, current_user_memory_set};
+use crate::console::getchar;
+use crate::error::{KernelError, Result};
+use crate::mm::{check_user_range, UserSlice};
+use crate::task::{
+    current_pid, current_user_token, yield_current_and_run_next,
+};
+use alloc::string::String;
 
+const FD_STDIN: usize = 0;
 const FD_STDOUT: usize = 1;
+// The user buffer is written in chunks of this size, so that the kernel memory
+// it takes doesn't depend on the size given by the user.
+const WRITE_CHUNK_SIZE: usize = 256;
 
-pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
+// Returns the number of bytes of the incomplete character at the end, or 0 if
+// the last character is complete or invalid.
+fn incomplete_char_len(bytes: &[u8]) -> usize {
+    for len in 1..=bytes.len().min(3) {
+        let byte = bytes[bytes.len() - len];
+        // Skips the continuation bytes until the first byte of the character.
+        if byte & 0xc0 != 0x80 {
+            let char_len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            return if char_len > len { len } else { 0 };
+        }
+    }
+    0
+}
+
+/// Reads from the given fd into the user buffer.
+/// For stdin, the current task yields until the first byte arrives, then it
+/// takes whatever else is available without waiting.
+/// Return the number of bytes read. It fails with EFAULT if the buffer is not
+/// writable.
+pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> Result<isize> {
+    match fd {
+        FD_STDIN => {
+            if len == 0 {
+                return Ok(0);
+            }
+            // Checks the buffer before waiting so that no input is lost; It's
+            // translated again afterwards, since the pages might be swapped
+            // out meanwhile.
+            let slice = UserSlice::new(current_user_token(), buf, len);
+            slice.buffers_mut()?;
+            let mut c = loop {
+                match getchar() {
+                    Some(c) => break c,
+                    None => yield_current_and_run_next(),
+                }
+            };
+            let buffers = slice.buffers_mut()?;
+            let mut read: usize = 0;
+            'fill: for buffer in buffers {
+                for byte in buffer.iter_mut() {
+                    *byte = c;
+                    read += 1;
+                    if read == len {
+                        break 'fill;
+                    }
+                    match getchar() {
+                        Some(next) => c = next,
+                        None => break 'fill,
+                    }
+                }
+            }
+            Ok(read as isize)
+        }
+        _ => Err(KernelError::BadFileDescriptor(fd)),
+    }
+}
+
+/// Writes the user buffer to the given fd.
+/// Return the number of bytes written, which is less than len if a part of the
+/// buffer is not readable. It fails with EFAULT if none of it is readable.
+pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> Result<isize> {
     match fd {
         FD_STDOUT => {
-            let buffers = translated_byte_buffer(
-                current_user_memory_set().exclusive_access().token(),
-                buf,
-                len,
-            );
+            check_user_range(buf as usize, len)?;
+            let token = current_user_token();
+            let mut chunk = [0u8; WRITE_CHUNK_SIZE];
+            // A character might cross chunks, the bytes of it in the last
+            // chunk are carried over to the head of the next one.
+            let mut carry = 0;
+            let mut written = 0;
+            let mut result = Ok(len as isize);
             #[cfg(debug_assertions)]
-            print!("[app {}] ", current_idx());
-            for buffer in buffers {
-                user_print!("{}", core::str::from_utf8(buffer).unwrap());
+            print!("[pid {}] ", current_pid());
+            while written < len {
+                let size = (WRITE_CHUNK_SIZE - carry).min(len - written);
+                let slice =
+                    UserSlice::new(token, buf.wrapping_add(written), size);
+                if let Err(err) =
+                    slice.copy_from_user(&mut chunk[carry..carry + size])
+                {
+                    if written > 0 {
+                        result = Ok(written as isize);
+                    } else {
+                        result = Err(err);
+                    }
+                    break;
+                }
+                written += size;
+                let filled = carry + size;
+                carry = if written < len {
+                    incomplete_char_len(&chunk[..filled])
+                } else {
+                    0
+                };
+                user_print!(
+                    "{}",
+                    String::from_utf8_lossy(&chunk[..filled - carry])
+                );
+                chunk.copy_within(filled - carry..filled, 0);
+            }
+            // The bytes carried over are left if the next chunk is not
+            // readable.
+            if carry > 0 {
+                user_print!("{}", String::from_utf8_lossy(&chunk[..carry]));
             }
             #[cfg(debug_assertions)]
             println!("");
-            len as isize
-        }
-        _ => {
-            panic!("Unsupported fd in sys_write!");
+            result
         }
+        _ => Err(KernelError::BadFileDescriptor(fd)),
     }
 }
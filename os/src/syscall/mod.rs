@@ -1,41 +1,102 @@
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_SLEEP: usize = 101;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_SET_PRIORITY: usize = 140;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_BRK: usize = 214;
 const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_FORK: usize = 220;
+const SYSCALL_EXEC: usize = 221;
 const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_TASK_INFO: usize = 410;
 
 mod fs;
 mod process;
 mod timer;
 
-use crate::task::current_idx;
+use crate::error::KernelError;
+use crate::task::{current_pid, record_current_syscall};
+use alloc::format;
 use fs::*;
 use process::*;
 use timer::*;
 
-pub fn syscall(syscall_id: usize, args: [usize; 3]) -> isize {
+fn syscall_name(syscall_id: usize) -> &'static str {
+    match syscall_id {
+        SYSCALL_READ => "SYSCALL_READ",
+        SYSCALL_WRITE => "SYSCALL_WRITE",
+        SYSCALL_EXIT => "SYSCALL_EXIT",
+        SYSCALL_SLEEP => "SYSCALL_SLEEP",
+        SYSCALL_YIELD => "SYSCALL_YIELD",
+        SYSCALL_SET_PRIORITY => "SYSCALL_SET_PRIORITY",
+        SYSCALL_GET_TIME => "SYSCALL_GET_TIME",
+        SYSCALL_GETPID => "SYSCALL_GETPID",
+        SYSCALL_BRK => "SYSCALL_BRK",
+        SYSCALL_MMAP => "SYSCALL_MMAP",
+        SYSCALL_MUNMAP => "SYSCALL_MUNMAP",
+        SYSCALL_MPROTECT => "SYSCALL_MPROTECT",
+        SYSCALL_FORK => "SYSCALL_FORK",
+        SYSCALL_EXEC => "SYSCALL_EXEC",
+        SYSCALL_WAITPID => "SYSCALL_WAITPID",
+        SYSCALL_TASK_INFO => "SYSCALL_TASK_INFO",
+        _ => "SYSCALL_UNSUPPORTED",
+    }
+}
+
+/// Dispatches the syscall given the id and six arguments from user space.
+/// The failures are returned as negative error numbers, e.g. -EINVAL.
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
+    record_current_syscall(syscall_id);
     debug!(
-        "[app {}] make a syscall: {}",
-        current_idx(),
-        match syscall_id {
-            SYSCALL_WRITE => "SYSCALL_WRITE",
-            SYSCALL_EXIT => "SYSCALL_EXIT",
-            SYSCALL_YIELD => "SYSCALL_YIELD",
-            SYSCALL_GET_TIME => "SYSCALL_GET_TIME",
-            SYSCALL_MMAP => "SYSCALL_MMAP",
-            SYSCALL_MUNMAP => "SYSCALL_MUNMAP",
-            _ => "SYSCALL_UNSUPPORTED",
-        }
+        "[pid {}] make a syscall: {}",
+        current_pid(),
+        syscall_name(syscall_id)
     );
 
-    match syscall_id {
+    let result = match syscall_id {
+        SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_SLEEP => sys_sleep(args[0]),
         SYSCALL_YIELD => sys_yield(),
+        SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
         SYSCALL_GET_TIME => sys_get_time(args[0] as *mut TimeVal, args[1]),
-        SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2]),
+        SYSCALL_MMAP => {
+            sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5])
+        }
+        SYSCALL_BRK => sys_brk(args[0]),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
-        _ => panic!("Unsupported syscall_id: {}", syscall_id),
+        SYSCALL_MPROTECT => sys_mprotect(args[0], args[1], args[2]),
+        SYSCALL_GETPID => sys_getpid(),
+        SYSCALL_FORK => sys_fork(),
+        SYSCALL_EXEC => sys_exec(args[0] as *const u8),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
+        _ => Err(KernelError::Unsupported(format!(
+            "Unsupported syscall_id: {}",
+            syscall_id
+        ))),
+    };
+    match result {
+        Ok(ret) => ret,
+        Err(err) => {
+            // The failures might be expected by the user, e.g. probing with an
+            // invalid argument, hence only logged in debug mode; Waiting for a
+            // child is polled, which is not worth a message at all.
+            if !matches!(err, KernelError::WouldBlock) {
+                debug!(
+                    "[pid {}] {} error: {}",
+                    current_pid(),
+                    syscall_name(syscall_id),
+                    err
+                );
+            }
+            -err.errno()
+        }
     }
 }
@@ -1,67 +1,299 @@
-use crate::config::PAGE_SIZE;
+use crate::config::{
+    BIG_STRIDE, MAX_SYSCALL_NUM, MICRO_PER_SEC, MILLI_PER_SEC, MMAP_END,
+    PAGE_SIZE,
+};
+use crate::error::{KernelError, Result};
+use crate::loader::get_app_data_by_name;
 use crate::mm::*;
 use crate::task::*;
+use crate::timer::get_time_us;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
 
 pub fn sys_exit(exit_code: i32) -> ! {
     println!("[kernel] Application exited with code {}", exit_code);
-    exit_current_and_run_next();
+    exit_current_and_run_next(exit_code);
 }
 
-pub fn sys_yield() -> isize {
-    suspend_current_and_run_next();
-    0
+pub fn sys_yield() -> Result<isize> {
+    yield_current_and_run_next();
+    Ok(0)
 }
 
-/// Creates a mapping area in the current user context.
+pub fn sys_getpid() -> Result<isize> {
+    Ok(current_pid() as isize)
+}
+
+/// Sets the priority of the current process, which only takes effect with
+/// stride scheduling.
 /// Args:
-///     - usize: the start of virtual address
-///     - len: the size of map area
-///     - prot: The first three bit is valid only, corresponding to RWX perm.
-/// Return 0 if success and -1 if fail.
-pub fn sys_mmap(start: usize, len: usize, prot: usize) -> isize {
-    if (prot & !0x7) > 0 || (prot & 0x7) == 0 || start % PAGE_SIZE != 0 {
-        return -1;
+///     - prio: the new priority, which must be at least 2 and at most
+///       BIG_STRIDE, so that the stride is at least 1.
+/// Return the new priority if success.
+pub fn sys_set_priority(prio: isize) -> Result<isize> {
+    if prio < 2 || prio as usize > BIG_STRIDE {
+        return Err(KernelError::InvalidArgument(format!(
+            "The priority {} is out of [2, {}]",
+            prio, BIG_STRIDE
+        )));
     }
-    let result = current_user_memory_set().exclusive_access().push_area(
-        MapArea::new(
-            VirtPageNumRange::new_from_va(start.into(), (start + len).into()),
-            Mapping::new_framed(),
-            MapPermission::U
-                | MapPermission::from_bits_truncate((prot << 1) as u8),
-        ),
-        true,
-        None,
+    current_task().unwrap().inner_exclusive_access().priority = prio as usize;
+    Ok(prio)
+}
+
+#[repr(C)]
+pub struct TaskInfo {
+    pub status: TaskStatus,
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    // Milliseconds since the task was scheduled for the first time.
+    pub time: usize,
+    // Microseconds spent in user space and kernel space respectively.
+    pub user_time: usize,
+    pub kernel_time: usize,
+    pub switch_count: usize,
+}
+
+/// Queries the statistics of the current process.
+/// Args:
+///     - ti: the user-space pointer to populate the TaskInfo.
+/// Return 0 if success.
+pub fn sys_task_info(ti: *mut TaskInfo) -> Result<isize> {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    let now = get_time_us();
+    let stats = &inner.stats;
+    let info = TaskInfo {
+        status: inner.status,
+        syscall_times: stats.syscall_times,
+        time: (now - stats.first_run_time.unwrap_or(now))
+            / (MICRO_PER_SEC / MILLI_PER_SEC),
+        user_time: stats.user_time,
+        kernel_time: stats.kernel_time,
+        switch_count: stats.switch_count,
+    };
+    let token = inner.get_user_token();
+    drop(inner);
+    UserPtr::new(token, ti).copy_to_user(&info)?;
+    Ok(0)
+}
+
+/// Creates a child process as a copy of the current one.
+/// Return the pid of the child in the parent and 0 in the child.
+pub fn sys_fork() -> Result<isize> {
+    let current_task = current_task().unwrap();
+    let new_task = current_task.fork()?;
+    let new_pid = new_task.getpid();
+    // The child gets 0 as the return value of fork.
+    new_task.inner_exclusive_access().get_trap_ctx().x[10] = 0;
+    add_task(new_task);
+    Ok(new_pid as isize)
+}
+
+/// Replaces the user space of the current process with the given app.
+/// Args:
+///     - path: the null-terminated name of the embedded app.
+/// It starts over from the entry of the app if success.
+pub fn sys_exec(path: *const u8) -> Result<isize> {
+    let path = read_cstr(current_user_token(), path)?;
+    let data = get_app_data_by_name(path.as_str()).ok_or_else(|| {
+        KernelError::NotFound(format!("Cannot find the app {}", path))
+    })?;
+    current_task().unwrap().exec(data)?;
+    Ok(0)
+}
+
+/// Reaps an exited child process and fetches its exit code.
+/// Args:
+///     - pid: the pid of the child, or -1 for any child.
+///     - exit_code_ptr: where to write the exit code of the child.
+/// Return the pid of the reaped child. It fails with ECHILD if there is no
+/// such child, EAGAIN if the child has not exited yet and EFAULT if the
+/// exit_code_ptr is not writable.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> Result<isize> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if !inner
+        .children
+        .iter()
+        .any(|child| pid == -1 || pid as usize == child.getpid())
+    {
+        return Err(KernelError::NoChild);
+    }
+    let idx = inner
+        .children
+        .iter()
+        .position(|child| {
+            child.inner_exclusive_access().is_zombie()
+                && (pid == -1 || pid as usize == child.getpid())
+        })
+        .ok_or(KernelError::WouldBlock)?;
+    let exit_code = inner.children[idx].inner_exclusive_access().exit_code;
+    let token = inner.get_user_token();
+    // Writing to user space might populate a page of the current task. The
+    // child is kept if it fails, so that it can be reaped again.
+    drop(inner);
+    if !exit_code_ptr.is_null() {
+        UserPtr::new(token, exit_code_ptr).copy_to_user(&exit_code)?;
+    }
+    let child = task.inner_exclusive_access().children.remove(idx);
+    // A zombie is unlinked from the scheduler and the wait queues once it
+    // exits, hence the parent holds the last reference; The child is released
+    // right after it's reaped, along with its PID and kernel stack.
+    debug_assert_eq!(Arc::strong_count(&child), 1);
+    Ok(child.getpid() as isize)
+}
+
+/// Changes the program break, i.e. the end of the heap.
+/// Args:
+///     - addr: the new program break, or 0 to query the current one.
+/// Return the program break after the change, which stays the same if fail.
+pub fn sys_brk(addr: usize) -> Result<isize> {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if addr == 0 || addr < inner.heap_bottom || addr > MMAP_END {
+        return Ok(inner.program_brk as isize);
+    }
+    let result = inner.memory_set.exclusive_access().resize_area(
+        VirtAddr::from(inner.heap_bottom).floor(),
+        VirtAddr::from(addr).ceil(),
     );
     match result {
-        Ok(_) => 0,
-        Err(err) => {
-            println!(
-                "[kernel] sys_mmap({}, {}, {:#b}) error: {}",
-                start, len, prot, err
-            );
-            -1
+        Ok(_) => inner.program_brk = addr,
+        Err(err) => println!("[kernel] sys_brk({:#x}) error: {}", addr, err),
+    }
+    Ok(inner.program_brk as isize)
+}
+
+bitflags! {
+    // The flags of mmap, the values are the same as Linux.
+    pub struct MmapFlags: usize {
+        // The pages are shared with the forked processes.
+        const SHARED = 1 << 0;
+        // The pages are copied on write in the forked processes.
+        const PRIVATE = 1 << 1;
+        // The address must be used as is rather than a hint. Unlike Linux, it
+        // fails if the range overlaps with the existing mappings.
+        const FIXED = 1 << 4;
+        // The pages are not backed by any file and filled with zeros.
+        const ANONYMOUS = 1 << 5;
+    }
+}
+
+// Converts the prot of mmap/mprotect into the permission of user pages, only
+// the first three bits are valid, corresponding to RWX perm. W without R is
+// rejected since it's reserved in the page table entry.
+fn user_permission(prot: usize) -> Result<MapPermission> {
+    if (prot & !0x7) > 0 || (prot & 0x7) == 0 || (prot & 0x3) == 0x2 {
+        return Err(KernelError::InvalidArgument(format!(
+            "Invalid prot {:#b}",
+            prot
+        )));
+    }
+    Ok(MapPermission::U | MapPermission::from_bits_truncate((prot << 1) as u8))
+}
+
+// Converts the range given by a page aligned start and the non-zero size into
+// VPNs.
+fn user_vpn_range(start: usize, len: usize) -> Result<VirtPageNumRange> {
+    match start.checked_add(len) {
+        Some(end) if start % PAGE_SIZE == 0 && len > 0 && end <= MMAP_END => {
+            Ok(VirtPageNumRange::new_from_va(start.into(), end.into()))
         }
+        _ => Err(KernelError::InvalidArgument(format!(
+            "Invalid range {:#x} with size {}",
+            start, len
+        ))),
     }
 }
 
-/// Creates and inserts a mapping area in the current user context.
+/// Creates a mapping area in the current user context.
 /// Args:
-///     - usize: the start of virtual address
+///     - addr: the start of virtual address. It's a hint unless MAP_FIXED is
+///       given, the kernel picks a free range if it's 0 or not available.
 ///     - len: the size of map area
 ///     - prot: The first three bit is valid only, corresponding to RWX perm.
-/// Return 0 if success and -1 if fail.
-pub fn sys_munmap(start: usize, len: usize) -> isize {
-    if start % PAGE_SIZE != 0 {
-        return -1;
+///     - flags: either MAP_SHARED or MAP_PRIVATE, with MAP_ANONYMOUS since
+///       there is no file to map yet, optionally with MAP_FIXED.
+///     - fd, offset: ignored for the anonymous mapping.
+/// Return the start of the mapped area if success.
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    _fd: usize,
+    _offset: usize,
+) -> Result<isize> {
+    let map_perm = user_permission(prot)?;
+    let flags = MmapFlags::from_bits(flags).ok_or_else(|| {
+        KernelError::InvalidArgument(format!("Invalid flags {:#x}", flags))
+    })?;
+    let shared = flags.contains(MmapFlags::SHARED);
+    if len == 0 || shared == flags.contains(MmapFlags::PRIVATE) {
+        return Err(KernelError::InvalidArgument(format!(
+            "Invalid size {} or flags {:?}",
+            len, flags
+        )));
     }
-    let result = current_user_memory_set().exclusive_access().drop_area(
-        VirtPageNumRange::new_from_va(start.into(), (start + len).into()),
-    );
-    match result {
-        Ok(_) => 0,
-        Err(err) => {
-            println!("[kernel] sys_munmap({}, {}) error: {}", start, len, err);
-            -1
-        }
+    if !flags.contains(MmapFlags::ANONYMOUS) {
+        return Err(KernelError::Unsupported(String::from(
+            "Only the anonymous mapping is supported",
+        )));
     }
+    let memory_set = current_user_memory_set();
+    let mut memory_set = memory_set.exclusive_access();
+    let hint = user_vpn_range(addr, len);
+    let vpn_range = match hint {
+        Ok(hint) if flags.contains(MmapFlags::FIXED) => hint,
+        Err(err) if flags.contains(MmapFlags::FIXED) => return Err(err),
+        Ok(hint) if addr != 0 && memory_set.is_free(hint) => hint,
+        _ => memory_set
+            .find_free_range((len + PAGE_SIZE - 1) / PAGE_SIZE)
+            .ok_or_else(|| {
+                KernelError::OutOfMemory(format!(
+                    "No free range for size {}",
+                    len
+                ))
+            })?,
+    };
+    let area = MapArea::new(vpn_range, Mapping::new_framed(), map_perm);
+    // The private frames are allocated on the first touch of each page, while
+    // the shared ones must exist before fork to be shared.
+    if shared {
+        memory_set.push_area(area.into_shared(), true, None)?;
+    } else {
+        memory_set.push_lazy_area(area, true)?;
+    }
+    Ok(VirtAddr::from(vpn_range.get_start()).0 as isize)
+}
+
+/// Removes the mapping of the pages in the current user context, the range
+/// might cover a part of a mapping area.
+/// Args:
+///     - start: the start of virtual address, which must be page aligned.
+///     - len: the size of the range.
+/// Return 0 if success.
+pub fn sys_munmap(start: usize, len: usize) -> Result<isize> {
+    let vpn_range = user_vpn_range(start, len)?;
+    current_user_memory_set()
+        .exclusive_access()
+        .unmap_range(vpn_range)?;
+    Ok(0)
+}
+
+/// Changes the permission of the pages mapped by mmap in the current user
+/// context, the range might cover a part of a mapping area.
+/// Args:
+///     - start: the start of virtual address, which must be page aligned.
+///     - len: the size of the range.
+///     - prot: The first three bit is valid only, corresponding to RWX perm.
+/// Return 0 if success.
+pub fn sys_mprotect(start: usize, len: usize, prot: usize) -> Result<isize> {
+    let vpn_range = user_vpn_range(start, len)?;
+    let map_perm = user_permission(prot)?;
+    current_user_memory_set()
+        .exclusive_access()
+        .protect_range(vpn_range, map_perm)?;
+    Ok(0)
 }
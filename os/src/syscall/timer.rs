@@ -1,9 +1,10 @@
-use core::mem::size_of;
-
-use crate::config::MICRO_PER_SEC;
-use crate::mm::translated_mut_byte_buffer;
-use crate::task::current_user_memory_set;
-use crate::timer::get_time_us;
+use crate::config::{MICRO_PER_SEC, MILLI_PER_SEC};
+use crate::error::Result;
+use crate::mm::UserPtr;
+use crate::task::{
+    block_current_and_run_next, current_task, current_user_token,
+};
+use crate::timer::{add_timer, get_time_us};
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -20,32 +21,25 @@ impl From<TimeVal> for usize {
 
 // The argument `ts` is a user-space pointer, we need translated it into
 // kernel-space before populating data; However, the pointer might map to a list
-// of non-contiguous memory segments hence we need to create one locally and
-// populate it into the segments finally.
-pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> isize {
+// of non-contiguous memory segments hence we create one locally and copy it
+// into the segments. It fails with EFAULT if the pointer is not writable.
+pub fn sys_get_time(ts: *mut TimeVal, _tz: usize) -> Result<isize> {
     let us = get_time_us();
-    let len = size_of::<TimeVal>();
     let now = TimeVal {
         sec: us / MICRO_PER_SEC,
         usec: us % MICRO_PER_SEC,
     };
-    // Translates the user's pointer into a list of buffers to populate.
-    let mut user_bufs = translated_mut_byte_buffer(
-        current_user_memory_set().exclusive_access().token(),
-        ts as *const u8,
-        len,
-    );
-    unsafe {
-        let ts_slice = core::slice::from_raw_parts(
-            (&now as *const TimeVal) as *const u8,
-            len,
-        );
-        // Copy the slice into the buffers to populate.
-        let mut offset = 0;
-        for buf in user_bufs.iter_mut() {
-            buf.copy_from_slice(&ts_slice[offset..(offset + buf.len())]);
-            offset = offset + buf.len();
-        }
-    }
-    0
+    UserPtr::new(current_user_token(), ts).copy_to_user(&now)?;
+    Ok(0)
+}
+
+/// Puts the current task to sleep for the given milliseconds, the task is
+/// not scheduled until then.
+/// Return 0 after it wakes up.
+pub fn sys_sleep(ms: usize) -> Result<isize> {
+    let expire_us = get_time_us()
+        .saturating_add(ms.saturating_mul(MICRO_PER_SEC / MILLI_PER_SEC));
+    add_timer(expire_us, current_task().unwrap());
+    block_current_and_run_next();
+    Ok(0)
 }
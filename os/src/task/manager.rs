@@ -0,0 +1,45 @@
+use super::scheduler::{Scheduler, SchedulerImpl};
+use super::TaskControlBlock;
+use crate::sync::UPSafeCell;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+// The task manager only keeps the tasks which are ready to run; The running
+// task is tracked by the processor instead. The scheduling policy is left to
+// the scheduler.
+pub struct TaskManager {
+    scheduler: SchedulerImpl,
+}
+
+impl TaskManager {
+    pub fn new() -> Self {
+        Self {
+            scheduler: SchedulerImpl::new(),
+        }
+    }
+    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.scheduler.add(task);
+    }
+    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.scheduler.fetch()
+    }
+    pub fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool {
+        self.scheduler.on_tick(task)
+    }
+    pub fn on_yield(&mut self, task: Arc<TaskControlBlock>) {
+        self.scheduler.on_yield(task);
+    }
+}
+
+lazy_static! {
+    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
+        unsafe { UPSafeCell::new(TaskManager::new()) };
+}
+
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.exclusive_access().add(task);
+}
+
+pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.exclusive_access().fetch()
+}
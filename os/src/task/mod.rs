@@ -1,195 +1,228 @@
 mod context;
+mod manager;
+mod pid;
+mod processor;
+mod scheduler;
 mod switch;
 mod task;
 
-use crate::loader::{get_app_data, get_num_app};
-use crate::mm::MemorySet;
-use crate::sync::UPSafeCell;
-use crate::trap::TrapContext;
+use crate::loader::{get_app_data_by_name, get_num_app, list_apps};
+use crate::sbi::shutdown;
+use crate::timer::{get_time_us, remove_timer};
 use alloc::sync::Arc;
-use alloc::vec::Vec;
 use context::TaskContext;
 use lazy_static::*;
+pub use manager::add_task;
+use manager::{fetch_task, TASK_MANAGER};
+pub use processor::{
+    current_pid, current_task, current_trap_ctx, current_user_memory_set,
+    current_user_token, run_tasks, schedule, take_current_task,
+};
 use switch::__switch;
-use task::{TaskControlBlock, TaskStatus};
-
-struct TaskManager {
-    // The number of tasks.
-    num_task: usize,
-    // The state of each task and current running task.
-    // Putting into under a wrapper since it's mutable.
-    inner: UPSafeCell<TaskManagerInner>,
-}
+pub use task::{TaskControlBlock, TaskStats, TaskStatus};
+
+// The exit code of a task killed by the kernel, as if by SIGKILL.
+const KILLED_EXIT_CODE: i32 = -9;
 
-struct TaskManagerInner {
-    // The control block of each task.
-    tasks: Vec<TaskControlBlock>,
-    // The current running task.
-    cur_task: usize,
-}
 // The macro lazy_static would postpone the initialization until the first time
 // variables are used.
 lazy_static! {
-    static ref TASK_MANAGER: TaskManager = {
-        println!("[kernel] Initializing task manager");
-        let num_app = get_num_app();
-        println!("[kernel] Num of applications: {}", num_app);
-
-        let mut tasks: Vec<TaskControlBlock> = Vec::new();
-
-        for i in 0..num_app {
-            tasks.push(match TaskControlBlock::new(get_app_data(i), i) {
-                Ok(tcb) => tcb,
-                Err(err) => {
-                    panic!("Failed initialize control block for app {} with error: {:?}", i, err)
-                }
-            })
+    // The init process spawns the shell and reaps the orphans, which are
+    // handed to it once their parent exits.
+    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new({
+        let elf_data = match get_app_data_by_name("initproc") {
+            Some(elf_data) => elf_data,
+            None => panic!("Cannot find the initproc app!"),
+        };
+        match TaskControlBlock::new(elf_data) {
+            Ok(tcb) => tcb,
+            Err(err) => panic!("Failed initialize the init process with error: {:?}", err),
         }
-        debug!("Initializing app/task done!");
-        TaskManager {
-            num_task: num_app,
-            inner: unsafe {
-                UPSafeCell::new(TaskManagerInner { tasks, cur_task: 0 })
-            },
-        }
-    };
+    });
 }
 
-impl TaskManager {
-    fn run_first_task(&self) -> ! {
-        let mut inner = TASK_MANAGER.inner.exclusive_access();
-        inner.tasks[0].status = TaskStatus::Running;
-        let next_task_ctx_ptr = &inner.tasks[0].ctx as *const TaskContext;
-        drop(inner);
-        // We just create an empty TaskContext which would be written unused
-        // registers in __switch. Since it's not registered in the TaskManager,
-        // it would never switch back to this fake TaskContext and never return.
-        let mut _unused = TaskContext::zero_init();
-        unsafe {
-            __switch(&mut _unused as *mut TaskContext, next_task_ctx_ptr);
-        }
-        panic!("Unreachable in TaskManager::run_first_task")
-    }
-
-    // Change the status of current `Running` task into `Ready`.
-    fn mark_current_suspended(&self) {
-        let mut inner = TASK_MANAGER.inner.exclusive_access();
-        let current_task_id = inner.cur_task;
-        let task = &mut inner.tasks[current_task_id];
-        if task.status != TaskStatus::Running {
-            panic!(
-                "Suspending a non-Running task {} with status {:?}",
-                current_task_id, task.status
-            );
-        }
+/// Adds the init process to the task manager. The rest of the apps are
+/// launched from the user shell.
+pub fn add_initproc() {
+    println!("[kernel] Initializing task manager");
+    println!("[kernel] Num of applications: {}", get_num_app());
+    list_apps();
+    add_task(INITPROC.clone());
+}
 
-        debug!("Suspending the running task {}", current_task_id);
+/// Suspends the current task since it's preempted, then run the next task.
+/// Other than the other function, this does return since when we switched back
+/// it needs to continue to run.
+pub fn suspend_current_and_run_next() {
+    suspend_current(false);
+}
 
-        task.status = TaskStatus::Ready;
-    }
+/// Gives up the CPU by the current task itself, then run the next task.
+pub fn yield_current_and_run_next() {
+    suspend_current(true);
+}
 
-    // Change the status of current `Running` task into `Exited`. Panic
-    // if the current task is not in Running status.
-    fn mark_current_exited(&self) {
-        let mut inner = TASK_MANAGER.inner.exclusive_access();
-        let current_task_id = inner.cur_task;
-        let task = &mut inner.tasks[current_task_id];
-        if task.status != TaskStatus::Running {
-            panic!(
-                "Exiting a non-Running task {} with status {:?}",
-                current_task_id, task.status
-            );
-        }
-        println!("[kernel] Exiting the running task {}", current_task_id);
-        task.status = TaskStatus::Exited;
-    }
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = TASK_MANAGER.inner.exclusive_access();
-        for i in 1..=self.num_task {
-            let next_task_id = (inner.cur_task + i) % self.num_task;
-            if inner.tasks[next_task_id].status == TaskStatus::Ready {
-                return Some(next_task_id);
-            }
-        }
-        None
-    }
-    fn run_next_task(&self) {
-        if let Some(next_task_id) = self.find_next_task() {
-            let mut inner = TASK_MANAGER.inner.exclusive_access();
-            let current_task_id = inner.cur_task;
-            let cur_task_ptr =
-                &mut inner.tasks[current_task_id].ctx as *mut TaskContext;
-            let next_task_ptr =
-                &inner.tasks[next_task_id].ctx as *const TaskContext;
-            inner.cur_task = next_task_id;
-            inner.tasks[next_task_id].status = TaskStatus::Running;
-            drop(inner);
-
-            debug!(
-                "switching task from {} to {}",
-                current_task_id, next_task_id
-            );
-
-            unsafe {
-                __switch(cur_task_ptr, next_task_ptr);
-            }
-        } else {
-            panic!("All tasks are exited normally.");
-        }
+// The task is put back by the scheduler through `on_yield` if it yields,
+// otherwise it's added as any other ready task.
+fn suspend_current(yielded: bool) {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_ctx_ptr = &mut task_inner.ctx as *mut TaskContext;
+    debug!("Suspending the running task {}", task.getpid());
+    task_inner.status = TaskStatus::Ready;
+    task_inner.stats.switch_out(get_time_us());
+    drop(task_inner);
+    if yielded {
+        TASK_MANAGER.exclusive_access().on_yield(task);
+    } else {
+        add_task(task);
     }
+    schedule(task_ctx_ptr);
+}
 
-    // Returns physical page number of page table in the current task context.
-    fn get_current_memory_set(&self) -> Arc<UPSafeCell<MemorySet>> {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.cur_task].memory_set.clone()
-    }
+/// Blocks the current task, then run the next task.
+/// The task is not put back to the ready queue, whoever holds it is
+/// responsible for waking it up by `wakeup_task`.
+pub fn block_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_ctx_ptr = &mut task_inner.ctx as *mut TaskContext;
+    debug!("Blocking the running task {}", task.getpid());
+    task_inner.status = TaskStatus::Blocked;
+    task_inner.stats.switch_out(get_time_us());
+    drop(task_inner);
+    drop(task);
+    schedule(task_ctx_ptr);
+}
 
-    // Returns trap context in the current task context.
-    fn get_current_trap_ctx(&self) -> &mut TrapContext {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.cur_task].trap_ctx_ppn.get_mut()
-    }
+/// Puts a blocked task back to the ready queue.
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let mut task_inner = task.inner_exclusive_access();
+    debug!("Waking up the task {}", task.getpid());
+    task_inner.status = TaskStatus::Ready;
+    drop(task_inner);
+    add_task(task);
+}
 
-    // Returns current task id.
-    fn get_current_idx(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        inner.cur_task
-    }
+/// Notifies the scheduler of a timer tick. Return true if the current task
+/// should be preempted.
+pub fn tick_current_task() -> bool {
+    let task = current_task().unwrap();
+    TASK_MANAGER.exclusive_access().on_tick(&task)
 }
 
-/// Run the first task.
-/// This function never returns since run_first_task never returns.
-pub fn run_first_task() -> ! {
-    TASK_MANAGER.run_first_task()
+/// Accounts the time the current task spent in user space when it traps.
+pub fn account_trap_enter() {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access()
+        .stats
+        .trap_enter(get_time_us());
 }
 
-/// Suspends the current task, then run the next task.
-/// Other than the other function, this does return since when we switched back
-/// it needs to continue to run.
-pub fn suspend_current_and_run_next() {
-    TASK_MANAGER.mark_current_suspended();
-    TASK_MANAGER.run_next_task();
+/// Accounts the time the current task spent in kernel space when it returns
+/// to user space.
+pub fn account_trap_return() {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access()
+        .stats
+        .trap_return(get_time_us());
 }
 
-/// Exits the current task, then run the next task.
-/// This function never returns since we never switched back to an exited task.
-pub fn exit_current_and_run_next() -> ! {
-    TASK_MANAGER.mark_current_exited();
-    TASK_MANAGER.run_next_task();
-    panic!("Unreachable in exit_current_and_run_next()");
+/// Counts a syscall made by the current task.
+pub fn record_current_syscall(syscall_id: usize) {
+    let task = current_task().unwrap();
+    task.inner_exclusive_access()
+        .stats
+        .record_syscall(syscall_id);
 }
 
-/// Return the address of root page table for the current task.
-pub fn current_user_memory_set() -> Arc<UPSafeCell<MemorySet>> {
-    TASK_MANAGER.get_current_memory_set()
+/// Kills the user task which takes the most frames, once a page fault cannot
+/// be handled since nothing could be reclaimed; The init process is spared
+/// unless it's the only one. The user pages of the victim are released right
+/// away, while the victim itself exits on its way back to user space, a
+/// sleeping one is woken up for that.
+/// It must be called where no task or memory set is borrowed.
+pub fn oom_kill() {
+    let mut victim: Option<(Arc<TaskControlBlock>, (bool, usize))> = None;
+    let mut tasks = vec![INITPROC.clone()];
+    while let Some(task) = tasks.pop() {
+        let inner = task.inner_exclusive_access();
+        tasks.extend(inner.children.iter().cloned());
+        if inner.is_zombie() || inner.killed {
+            continue;
+        }
+        let pages = inner.memory_set.exclusive_access().user_pages();
+        drop(inner);
+        let key = (!Arc::ptr_eq(&task, &INITPROC), pages);
+        if victim.as_ref().map_or(true, |(_, best)| key > *best) {
+            victim = Some((task, key));
+        }
+    }
+    let (task, (_, pages)) = match victim {
+        Some(victim) => victim,
+        None => return,
+    };
+    println!(
+        "[kernel] Out of memory, killing task {} which takes {} pages",
+        task.getpid(),
+        pages
+    );
+    let mut inner = task.inner_exclusive_access();
+    inner.killed = true;
+    inner.memory_set.exclusive_access().recycle_user_pages();
+    let blocked = inner.status == TaskStatus::Blocked;
+    drop(inner);
+    if blocked && remove_timer(&task) {
+        wakeup_task(task);
+    }
 }
 
-/// Return the address of TrapContext for the current task.
-pub fn current_trap_ctx() -> &'static mut TrapContext {
-    TASK_MANAGER.get_current_trap_ctx()
+/// Exits the current task if it's killed, before it returns to user space.
+pub fn exit_current_if_killed() {
+    let killed = current_task().unwrap().inner_exclusive_access().killed;
+    if killed {
+        exit_current_and_run_next(KILLED_EXIT_CODE);
+    }
 }
 
-/// Return the current running app idx.
-pub fn current_idx() -> usize {
-    TASK_MANAGER.get_current_idx()
+/// Exits the current task, then run the next task.
+/// The task turns into a zombie which keeps the exit code until its parent
+/// reaps it, while its children are handed to the init process.
+/// This function never returns since we never switched back to an exited task.
+pub fn exit_current_and_run_next(exit_code: i32) -> ! {
+    let task = take_current_task().unwrap();
+    println!(
+        "[kernel] Exiting the running task {} with code {}",
+        task.getpid(),
+        exit_code
+    );
+    // Nobody is left to reap the orphans once the init process exits, the
+    // exit code of it tells whether the whole run succeeded.
+    if Arc::ptr_eq(&task, &INITPROC) {
+        println!("[kernel] Init process exited, powering off");
+        shutdown(exit_code != 0);
+    }
+    // The zombie must not be woken up again, and the parent holds the last
+    // reference to it once it's unlinked from the timer queue.
+    remove_timer(&task);
+    let mut inner = task.inner_exclusive_access();
+    inner.status = TaskStatus::Zombie;
+    inner.exit_code = exit_code;
+    let mut initproc_inner = INITPROC.inner_exclusive_access();
+    for child in inner.children.iter() {
+        child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+        initproc_inner.children.push(child.clone());
+    }
+    inner.children.clear();
+    drop(initproc_inner);
+    // Recycles the user space right away; The kernel stack and the page table
+    // are kept until the parent reaps this task.
+    inner.memory_set.exclusive_access().recycle_data_pages();
+    drop(inner);
+    drop(task);
+    debug!("Kernel heap after exiting: {:?}", crate::mm::heap_stats());
+    // The context of an exited task is never used again.
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut TaskContext);
+    panic!("Unreachable in exit_current_and_run_next()");
 }
@@ -0,0 +1,114 @@
+// The pid allocator and the kernel stack of each process. Both are released
+// automatically by RAII once the process is reaped.
+use crate::config::kernel_stack_position;
+use crate::error::Result;
+use crate::mm::{
+    MapArea, MapPermission, Mapping, VirtPageNumRange, KERNEL_SPACE,
+};
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+struct PidAllocator {
+    // The smallest pid which has never been allocated.
+    current: usize,
+    // A list contains recycled pids.
+    recycled: Vec<usize>,
+}
+
+pub struct PidHandle(pub usize);
+
+// The kernel stack of a process, it's placed in the kernel space according
+// to the pid.
+pub struct KernelStack {
+    pid: usize,
+}
+
+lazy_static! {
+    static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
+        unsafe { UPSafeCell::new(PidAllocator::new()) };
+}
+
+// We only expose alloc method and we depend on RAII scheme to dealloc.
+pub fn pid_alloc() -> PidHandle {
+    PID_ALLOCATOR.exclusive_access().alloc()
+}
+
+impl PidAllocator {
+    fn new() -> Self {
+        PidAllocator {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    fn alloc(&mut self) -> PidHandle {
+        if let Some(pid) = self.recycled.pop() {
+            PidHandle(pid)
+        } else {
+            self.current += 1;
+            PidHandle(self.current - 1)
+        }
+    }
+    fn dealloc(&mut self, pid: usize) {
+        if pid >= self.current || self.recycled.iter().any(|&v| v == pid) {
+            panic!("Pid {} has not been allocated!", pid);
+        }
+        self.recycled.push(pid);
+    }
+}
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+impl KernelStack {
+    // Maps the kernel stack for the given pid in the kernel space.
+    pub fn new(pid_handle: &PidHandle) -> Result<Self> {
+        let pid = pid_handle.0;
+        let (kernel_stack_bottom, kernel_stack_top) =
+            kernel_stack_position(pid);
+        debug!(
+            "kernel bottom {:#x} and top {:#x}",
+            kernel_stack_bottom, kernel_stack_top
+        );
+        // The area is droppable so that it can be removed once the process
+        // is reaped.
+        KERNEL_SPACE.exclusive_access().push_area(
+            MapArea::new(
+                VirtPageNumRange::new_from_va(
+                    kernel_stack_bottom.into(),
+                    kernel_stack_top.into(),
+                ),
+                Mapping::new_framed(),
+                MapPermission::R | MapPermission::W,
+            ),
+            true,
+            None,
+        )?;
+        Ok(KernelStack { pid })
+    }
+    pub fn get_top(&self) -> usize {
+        let (_, kernel_stack_top) = kernel_stack_position(self.pid);
+        kernel_stack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, kernel_stack_top) =
+            kernel_stack_position(self.pid);
+        if let Err(err) = KERNEL_SPACE.exclusive_access().drop_area(
+            VirtPageNumRange::new_from_va(
+                kernel_stack_bottom.into(),
+                kernel_stack_top.into(),
+            ),
+        ) {
+            panic!(
+                "Failed to drop the kernel stack of pid {}: {}",
+                self.pid, err
+            );
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use super::__switch;
+use super::{fetch_task, TaskStatus};
+use super::{TaskContext, TaskControlBlock};
+use crate::mm::MemorySet;
+use crate::sbi::shutdown;
+use crate::sync::UPSafeCell;
+use crate::timer::{check_timer, get_time_us, has_timer};
+use crate::trap::TrapContext;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+// The processor tracks the running task. Switching between two tasks always
+// goes through the idle control flow in `run_tasks`.
+pub struct Processor {
+    // The task currently running on this processor.
+    current: Option<Arc<TaskControlBlock>>,
+    // The TaskContext of the idle control flow, i.e. `run_tasks`.
+    idle_task_ctx: TaskContext,
+}
+
+impl Processor {
+    pub fn new() -> Self {
+        Self {
+            current: None,
+            idle_task_ctx: TaskContext::zero_init(),
+        }
+    }
+    fn get_idle_task_ctx_ptr(&mut self) -> *mut TaskContext {
+        &mut self.idle_task_ctx as *mut _
+    }
+    pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.current.take()
+    }
+    pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
+        self.current.as_ref().map(Arc::clone)
+    }
+}
+
+lazy_static! {
+    pub static ref PROCESSOR: UPSafeCell<Processor> =
+        unsafe { UPSafeCell::new(Processor::new()) };
+}
+
+/// The idle control flow: keeps fetching a ready task and switching to it.
+/// Whenever the running task gives up the CPU, it switches back here.
+pub fn run_tasks() -> ! {
+    loop {
+        let mut processor = PROCESSOR.exclusive_access();
+        if let Some(task) = fetch_task() {
+            let idle_task_ctx_ptr = processor.get_idle_task_ctx_ptr();
+            let mut task_inner = task.inner_exclusive_access();
+            let next_task_ctx_ptr = &task_inner.ctx as *const TaskContext;
+            task_inner.status = TaskStatus::Running;
+            task_inner.stats.switch_in(get_time_us());
+            drop(task_inner);
+            debug!("switching to task {}", task.getpid());
+            processor.current = Some(task);
+            drop(processor);
+            unsafe {
+                __switch(idle_task_ctx_ptr, next_task_ctx_ptr);
+            }
+        } else if has_timer() {
+            // Nothing is ready but some tasks are sleeping, waits for them
+            // here since the timer interrupt is not taken in the kernel.
+            drop(processor);
+            check_timer();
+        } else {
+            println!("[kernel] All tasks are exited normally.");
+            shutdown(false);
+        }
+    }
+}
+
+/// Takes the current task out of the processor.
+pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
+    PROCESSOR.exclusive_access().take_current()
+}
+
+/// Returns a copy of the current task.
+pub fn current_task() -> Option<Arc<TaskControlBlock>> {
+    PROCESSOR.exclusive_access().current()
+}
+
+/// Return the memory set of the current task.
+pub fn current_user_memory_set() -> Arc<UPSafeCell<MemorySet>> {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .memory_set
+        .clone()
+}
+
+/// Return the satp token of the current task.
+pub fn current_user_token() -> usize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_user_token()
+}
+
+/// Return the address of TrapContext for the current task.
+pub fn current_trap_ctx() -> &'static mut TrapContext {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .get_trap_ctx()
+}
+
+/// Return the pid of the current task.
+pub fn current_pid() -> usize {
+    current_task().unwrap().getpid()
+}
+
+/// Switches from the given task context back to the idle control flow.
+pub fn schedule(switched_task_ctx_ptr: *mut TaskContext) {
+    let mut processor = PROCESSOR.exclusive_access();
+    let idle_task_ctx_ptr = processor.get_idle_task_ctx_ptr();
+    drop(processor);
+    unsafe {
+        __switch(switched_task_ctx_ptr, idle_task_ctx_ptr);
+    }
+}
@@ -0,0 +1,27 @@
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+// Runs the tasks in the order they become ready. A task is never preempted
+// and it runs until it yields, blocks or exits.
+pub struct FifoScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Scheduler for FifoScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.ready_queue.pop_front()
+    }
+    fn on_tick(&mut self, _task: &Arc<TaskControlBlock>) -> bool {
+        false
+    }
+}
@@ -0,0 +1,52 @@
+// The scheduling policy is selected at compile time by one of the features:
+// - sched-fifo: runs each task until it gives up the CPU.
+// - sched-rr: round-robin with a fixed time slice.
+// - sched-stride: stride scheduling based on the priority of each task.
+#[cfg(feature = "sched-fifo")]
+mod fifo;
+#[cfg(feature = "sched-rr")]
+mod round_robin;
+#[cfg(feature = "sched-stride")]
+mod stride;
+
+use super::TaskControlBlock;
+use alloc::sync::Arc;
+
+#[cfg(not(any(
+    feature = "sched-fifo",
+    feature = "sched-rr",
+    feature = "sched-stride"
+)))]
+compile_error!("One of the sched-* features must be enabled!");
+#[cfg(any(
+    all(feature = "sched-fifo", feature = "sched-rr"),
+    all(feature = "sched-fifo", feature = "sched-stride"),
+    all(feature = "sched-rr", feature = "sched-stride")
+))]
+compile_error!("Only one of the sched-* features can be enabled!");
+
+// A scheduler keeps the tasks which are ready to run and decides which one
+// runs next. The running task is not kept in the scheduler, and it's added
+// back once it yields or is preempted.
+pub trait Scheduler {
+    fn new() -> Self;
+    // Adds a task which is ready to run.
+    fn add(&mut self, task: Arc<TaskControlBlock>);
+    // Removes and returns the task to run next.
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>>;
+    // Called on each timer tick with the running task. Returns true if the
+    // running task should be preempted.
+    fn on_tick(&mut self, task: &Arc<TaskControlBlock>) -> bool;
+    // Called when the running task gives up the CPU by itself, which puts it
+    // back like a preempted one unless the policy tells them apart.
+    fn on_yield(&mut self, task: Arc<TaskControlBlock>) {
+        self.add(task);
+    }
+}
+
+#[cfg(feature = "sched-fifo")]
+pub type SchedulerImpl = fifo::FifoScheduler;
+#[cfg(feature = "sched-rr")]
+pub type SchedulerImpl = round_robin::RoundRobinScheduler;
+#[cfg(feature = "sched-stride")]
+pub type SchedulerImpl = stride::StrideScheduler;
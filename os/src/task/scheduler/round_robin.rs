@@ -0,0 +1,35 @@
+use super::Scheduler;
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+// The number of timer ticks a task runs before it's preempted.
+const TIME_SLICE: usize = 5;
+
+// Runs the tasks in turn, each for at most a time slice.
+pub struct RoundRobinScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    // The ticks left in the time slice of the running task.
+    ticks_left: usize,
+}
+
+impl Scheduler for RoundRobinScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+            ticks_left: TIME_SLICE,
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        let task = self.ready_queue.pop_front()?;
+        self.ticks_left = TIME_SLICE;
+        Some(task)
+    }
+    fn on_tick(&mut self, _task: &Arc<TaskControlBlock>) -> bool {
+        self.ticks_left = self.ticks_left.saturating_sub(1);
+        self.ticks_left == 0
+    }
+}
@@ -0,0 +1,50 @@
+use super::Scheduler;
+use crate::config::BIG_STRIDE;
+use crate::task::TaskControlBlock;
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+// Fetches the task with the smallest pass, the tasks with the same pass are
+// fetched in FIFO order. The pass of a task grows by BIG_STRIDE / priority
+// each time it's fetched, so its CPU share is proportional to the priority.
+pub struct StrideScheduler {
+    ready_queue: VecDeque<Arc<TaskControlBlock>>,
+}
+
+// Passes are compared by their difference so that the comparison still holds
+// after a pass overflows, as long as the passes are within half of usize.
+fn pass_less_than(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+impl Scheduler for StrideScheduler {
+    fn new() -> Self {
+        Self {
+            ready_queue: VecDeque::new(),
+        }
+    }
+    fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.ready_queue.push_back(task);
+    }
+    fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        // The index and the pass of the task to run next.
+        let mut next: Option<(usize, usize)> = None;
+        for (idx, task) in self.ready_queue.iter().enumerate() {
+            let pass = task.inner_exclusive_access().pass;
+            match next {
+                Some((_, min_pass)) if !pass_less_than(pass, min_pass) => {}
+                _ => next = Some((idx, pass)),
+            }
+        }
+        let task = self.ready_queue.remove(next?.0)?;
+        let mut task_inner = task.inner_exclusive_access();
+        task_inner.pass = task_inner
+            .pass
+            .wrapping_add(BIG_STRIDE / task_inner.priority);
+        drop(task_inner);
+        Some(task)
+    }
+    fn on_tick(&mut self, _task: &Arc<TaskControlBlock>) -> bool {
+        true
+    }
+}
@@ -4,35 +4,133 @@ use crate::error::Result;
 use crate::mm::*;
 use crate::sync::UPSafeCell;
 use crate::trap::{trap_handler, TrapContext};
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use core::cell::RefMut;
 
-use crate::config::{kernel_stack_position, TRAP_CONTEXT_ADDR};
+use super::pid::{pid_alloc, KernelStack, PidHandle};
+use crate::config::{DEFAULT_PRIORITY, MAX_SYSCALL_NUM, TRAP_CONTEXT_ADDR};
 
 pub struct TaskControlBlock {
+    // Immutable after the task is created.
+    pub pid: PidHandle,
+    pub kernel_stack: KernelStack,
+    // Mutable states of the task.
+    inner: UPSafeCell<TaskControlBlockInner>,
+}
+
+pub struct TaskControlBlockInner {
     pub ctx: TaskContext,
     pub status: TaskStatus,
     pub memory_set: Arc<UPSafeCell<MemorySet>>,
     // The physical address of Trap context.
     pub trap_ctx_ppn: PhysPageNum,
     pub base_size: usize,
+    // The heap is in [heap_bottom, program_brk), which is changed by brk.
+    pub heap_bottom: usize,
+    pub program_brk: usize,
+    // The parent is a weak reference so that there is no reference cycle
+    // between the parent and the children.
+    pub parent: Option<Weak<TaskControlBlock>>,
+    pub children: Vec<Arc<TaskControlBlock>>,
+    // Kept after the task exits until the parent reaps it by waitpid.
+    pub exit_code: i32,
+    // Set once the task is killed, e.g. by the OOM killer; It exits instead
+    // of returning to user space.
+    pub killed: bool,
+    // Stride scheduling: the task with the smallest pass runs next and its
+    // pass grows by BIG_STRIDE / priority each time it's scheduled.
+    pub priority: usize,
+    pub pass: usize,
+    pub stats: TaskStats,
+}
+
+// The CPU time accounting of a task, all the time is in microseconds.
+pub struct TaskStats {
+    // When the task is scheduled for the first time.
+    pub first_run_time: Option<usize>,
+    pub user_time: usize,
+    pub kernel_time: usize,
+    // How many times the task is switched to.
+    pub switch_count: usize,
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    // When the task last switched between user and kernel, or was switched
+    // to; The time since then is accounted at the next transition.
+    checkpoint: usize,
+}
+
+impl TaskStats {
+    pub fn new() -> Self {
+        Self {
+            first_run_time: None,
+            user_time: 0,
+            kernel_time: 0,
+            switch_count: 0,
+            syscall_times: [0; MAX_SYSCALL_NUM],
+            checkpoint: 0,
+        }
+    }
+    pub fn switch_in(&mut self, now: usize) {
+        self.first_run_time.get_or_insert(now);
+        self.switch_count += 1;
+        self.checkpoint = now;
+    }
+    pub fn switch_out(&mut self, now: usize) {
+        self.kernel_time += now - self.checkpoint;
+        self.checkpoint = now;
+    }
+    pub fn trap_enter(&mut self, now: usize) {
+        self.user_time += now - self.checkpoint;
+        self.checkpoint = now;
+    }
+    pub fn trap_return(&mut self, now: usize) {
+        self.kernel_time += now - self.checkpoint;
+        self.checkpoint = now;
+    }
+    pub fn record_syscall(&mut self, syscall_id: usize) {
+        if syscall_id < MAX_SYSCALL_NUM {
+            self.syscall_times[syscall_id] += 1;
+        }
+    }
 }
 
 /* The state transition of a task:
 
-                 +-------+
-    +----------->| Ready |       +--------+
-    | Init       +-+-----+       | Exited |
-+---+----+ run_as_ |   ^         +--------+
-| UnInit |  next   v   | yield       ^
-+--------+      +------+--+  Exit    |
-                | Running +----------+
-                +---------+
+                 +-------+  wakeup  +---------+
+    +----------->| Ready |<---------+ Blocked |
+    | Init/fork  +-+-----+          +---------+
++---+----+ run_as_ |   ^                 ^
+| UnInit |  next   v   | yield           | sleep
++--------+      +------+--+--------------+
+                | Running |
+                +----+----+
+                     | Exit
+                     v
+                +--------+  waitpid
+                | Zombie +----------> (Reaped)
+                +--------+
  */
+// It's a part of TaskInfo for sys_task_info, hence the C representation.
+#[repr(C)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskStatus {
     Ready,
     Running,
-    Exited,
+    // Waiting for an event, e.g. the deadline of sleep.
+    Blocked,
+    Zombie,
+}
+
+impl TaskControlBlockInner {
+    pub fn get_trap_ctx(&self) -> &'static mut TrapContext {
+        self.trap_ctx_ppn.get_mut()
+    }
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.exclusive_access().token()
+    }
+    pub fn is_zombie(&self) -> bool {
+        self.status == TaskStatus::Zombie
+    }
 }
 
 impl TaskControlBlock {
@@ -40,40 +138,42 @@ impl TaskControlBlock {
     // - Initializes memory set(mapping and page table) in the user space.
     // - Allocates specific memory as kernel stack for this app in the kernel
     // space.
-    pub fn new(elf_data: &[u8], app_id: usize) -> Result<Self> {
-        debug!("Initializing task control block for app: {}", app_id);
-        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data)?;
+    pub fn new(elf_data: &[u8]) -> Result<Self> {
+        let pid_handle = pid_alloc();
+        debug!("Initializing task control block for pid: {}", pid_handle.0);
+        let (memory_set, user_sp, heap_bottom, entry_point) =
+            MemorySet::from_elf(elf_data)?;
         let trap_ctx_ppn = memory_set
             .translate(VirtAddr::from(TRAP_CONTEXT_ADDR).into())
             .unwrap();
-        let task_status = TaskStatus::Ready;
-        let (kernel_stack_bottom, kernel_stack_top) =
-            kernel_stack_position(app_id);
-        debug!(
-            "kernel bottom {:#x} and top {:#x}",
-            kernel_stack_bottom, kernel_stack_top
-        );
-        KERNEL_SPACE.exclusive_access().push_area(
-            MapArea::new(
-                VirtPageNumRange::new_from_va(
-                    kernel_stack_bottom.into(),
-                    kernel_stack_top.into(),
-                ),
-                Mapping::new_framed(),
-                MapPermission::R | MapPermission::W,
-            ),
-            false,
-            None,
-        )?;
+        let kernel_stack = KernelStack::new(&pid_handle)?;
+        let kernel_stack_top = kernel_stack.get_top();
+        let memory_set = Arc::new(unsafe { UPSafeCell::new(memory_set) });
+        register_memory_set(&memory_set);
         let task_control_block = Self {
-            ctx: TaskContext::goto_trap_return(kernel_stack_top),
-            status: task_status,
-            memory_set: Arc::new(unsafe { UPSafeCell::new(memory_set) }),
-            trap_ctx_ppn,
-            base_size: user_sp,
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    ctx: TaskContext::goto_trap_return(kernel_stack_top),
+                    status: TaskStatus::Ready,
+                    memory_set,
+                    trap_ctx_ppn,
+                    base_size: user_sp,
+                    heap_bottom,
+                    program_brk: heap_bottom,
+                    parent: None,
+                    children: Vec::new(),
+                    exit_code: 0,
+                    killed: false,
+                    priority: DEFAULT_PRIORITY,
+                    pass: 0,
+                    stats: TaskStats::new(),
+                })
+            },
         };
-        debug!("trap_return {:?}", task_control_block.ctx);
-        let trap_ctx = task_control_block.get_trap_ctx();
+        let trap_ctx =
+            task_control_block.inner_exclusive_access().get_trap_ctx();
         *trap_ctx = TrapContext::app_init_context(
             entry_point,
             user_sp,
@@ -81,10 +181,96 @@ impl TaskControlBlock {
             kernel_stack_top,
             trap_handler as usize,
         );
-        debug!("Initialization of app {} done", app_id);
+        debug!("Initialization of pid {} done", task_control_block.getpid());
         Ok(task_control_block)
     }
-    fn get_trap_ctx(&self) -> &'static mut TrapContext {
-        self.trap_ctx_ppn.get_mut()
+
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+
+    // Replaces the user space of the current task with the given elf, the
+    // pid, kernel stack and the parent/children links are kept.
+    pub fn exec(&self, elf_data: &[u8]) -> Result<()> {
+        let (memory_set, user_sp, heap_bottom, entry_point) =
+            MemorySet::from_elf(elf_data)?;
+        let trap_ctx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT_ADDR).into())
+            .unwrap();
+        let memory_set = Arc::new(unsafe { UPSafeCell::new(memory_set) });
+        register_memory_set(&memory_set);
+        let mut inner = self.inner_exclusive_access();
+        // The old memory set is dropped here and its frames are recycled.
+        inner.memory_set = memory_set;
+        inner.trap_ctx_ppn = trap_ctx_ppn;
+        inner.base_size = user_sp;
+        inner.heap_bottom = heap_bottom;
+        inner.program_brk = heap_bottom;
+        *inner.get_trap_ctx() = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            self.kernel_stack.get_top(),
+            trap_handler as usize,
+        );
+        Ok(())
+    }
+
+    // Creates a child task which has a copy of the user space of the current
+    // task. The child returns to user space right after the fork syscall with
+    // the same TrapContext, except the kernel stack.
+    pub fn fork(self: &Arc<Self>) -> Result<Arc<Self>> {
+        let mut parent_inner = self.inner_exclusive_access();
+        let memory_set = MemorySet::from_existed_user(
+            &mut parent_inner.memory_set.exclusive_access(),
+        )?;
+        let trap_ctx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT_ADDR).into())
+            .unwrap();
+        let pid_handle = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid_handle)?;
+        let kernel_stack_top = kernel_stack.get_top();
+        let memory_set = Arc::new(unsafe { UPSafeCell::new(memory_set) });
+        register_memory_set(&memory_set);
+        let task_control_block = Arc::new(Self {
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    ctx: TaskContext::goto_trap_return(kernel_stack_top),
+                    status: TaskStatus::Ready,
+                    memory_set,
+                    trap_ctx_ppn,
+                    base_size: parent_inner.base_size,
+                    heap_bottom: parent_inner.heap_bottom,
+                    program_brk: parent_inner.program_brk,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    killed: false,
+                    // Inherits the pass so that the child does not take
+                    // over the CPU until it catches up with the others.
+                    priority: parent_inner.priority,
+                    pass: parent_inner.pass,
+                    stats: TaskStats::new(),
+                })
+            },
+        });
+        parent_inner.children.push(task_control_block.clone());
+        // The TrapContext has been copied from the parent, only the kernel
+        // stack differs.
+        let trap_ctx =
+            task_control_block.inner_exclusive_access().get_trap_ctx();
+        trap_ctx.kernel_sp = kernel_stack_top;
+        debug!(
+            "Forked pid {} from pid {}",
+            task_control_block.getpid(),
+            self.getpid()
+        );
+        Ok(task_control_block)
     }
 }
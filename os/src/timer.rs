@@ -1,10 +1,17 @@
-use crate::config::{CLOCK_FREQ, MICRO_PER_SEC};
+use crate::board::clock_freq;
+use crate::config::MICRO_PER_SEC;
 use crate::sbi::set_timer;
+use crate::sync::UPSafeCell;
+use crate::task::{wakeup_task, TaskControlBlock};
+use alloc::collections::BinaryHeap;
+use alloc::sync::Arc;
+use core::cmp::Ordering;
+use lazy_static::*;
 use riscv::register::time;
 const TICKS_PER_SEC: usize = 100;
 
 pub fn set_next_trigger() {
-    set_timer(get_time() + CLOCK_FREQ / TICKS_PER_SEC);
+    set_timer(get_time() + clock_freq() / TICKS_PER_SEC);
 }
 
 pub fn get_time() -> usize {
@@ -12,5 +19,75 @@ pub fn get_time() -> usize {
 }
 
 pub fn get_time_us() -> usize {
-    time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
+    time::read() / (clock_freq() / MICRO_PER_SEC)
+}
+
+// A sleeping task which is woken up once the time passes the deadline.
+pub struct TimerCondVar {
+    pub expire_us: usize,
+    pub task: Arc<TaskControlBlock>,
+}
+
+impl PartialEq for TimerCondVar {
+    fn eq(&self, other: &Self) -> bool {
+        self.expire_us == other.expire_us
+    }
+}
+
+impl Eq for TimerCondVar {}
+
+impl PartialOrd for TimerCondVar {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The order is reversed so that the BinaryHeap, which is a max-heap, pops the
+// earliest deadline first.
+impl Ord for TimerCondVar {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.expire_us.cmp(&self.expire_us)
+    }
+}
+
+lazy_static! {
+    static ref TIMERS: UPSafeCell<BinaryHeap<TimerCondVar>> =
+        unsafe { UPSafeCell::new(BinaryHeap::new()) };
+}
+
+/// Parks the task in the timer queue until the given deadline.
+pub fn add_timer(expire_us: usize, task: Arc<TaskControlBlock>) {
+    TIMERS
+        .exclusive_access()
+        .push(TimerCondVar { expire_us, task });
+}
+
+/// Takes the task out of the timer queue before its deadline, e.g. once it's
+/// killed. Return true if it was sleeping.
+pub fn remove_timer(task: &Arc<TaskControlBlock>) -> bool {
+    let mut timers = TIMERS.exclusive_access();
+    let len = timers.len();
+    let rest: BinaryHeap<TimerCondVar> = timers
+        .drain()
+        .filter(|timer| !Arc::ptr_eq(&timer.task, task))
+        .collect();
+    *timers = rest;
+    timers.len() < len
+}
+
+/// Wakes up all the tasks whose deadline has passed.
+pub fn check_timer() {
+    let now = get_time_us();
+    let mut timers = TIMERS.exclusive_access();
+    while let Some(timer) = timers.peek() {
+        if timer.expire_us > now {
+            break;
+        }
+        wakeup_task(timers.pop().unwrap().task);
+    }
+}
+
+/// Return true if any task is sleeping.
+pub fn has_timer() -> bool {
+    !TIMERS.exclusive_access().is_empty()
 }
@@ -1,8 +1,11 @@
 pub mod context;
 
 use crate::config::{TRAMPOLINE_ADDR, TRAP_CONTEXT_ADDR};
+use crate::error::KernelError;
+use crate::mm::{balance_frames, MapPermission, VirtAddr};
 use crate::sbi::shutdown;
-use crate::{syscall::syscall, task::*, timer::set_next_trigger};
+use crate::timer::{check_timer, set_next_trigger};
+use crate::{syscall::syscall, task::*};
 use core::arch::{asm, global_asm};
 use riscv::register::sie;
 use riscv::register::{
@@ -39,14 +42,21 @@ fn set_user_trap_entry() {
 
 pub fn set_shutdown_trap_entry() {
     unsafe {
-        stvec::write(shutdown as usize, TrapMode::Direct);
+        stvec::write(shutdown_on_trap as usize, TrapMode::Direct);
     }
 }
 
+// Any trap at this point means the kernel is already broken.
+fn shutdown_on_trap() -> ! {
+    shutdown(true)
+}
+
 pub fn trap_return() -> ! {
+    exit_current_if_killed();
     set_user_trap_entry();
+    account_trap_return();
     let trap_ctx_ptr = TRAP_CONTEXT_ADDR;
-    let user_satp = current_user_memory_set().exclusive_access().token();
+    let user_satp = current_user_token();
     extern "C" {
         fn __alltraps();
         fn __restore();
@@ -78,6 +88,10 @@ pub fn trap_from_kernel() -> ! {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    account_trap_enter();
+    // No memory set is borrowed here, hence the pages of the current task
+    // might be swapped out as well as the others.
+    balance_frames();
     let ctx = current_trap_ctx();
     let scause = scause::read();
     let stval = stval::read();
@@ -86,31 +100,70 @@ pub fn trap_handler() -> ! {
         // Triggered from user space, executing system call.
         Trap::Exception(Exception::UserEnvCall) => {
             ctx.sepc += 4;
-            ctx.x[10] =
-                syscall(ctx.x[17], [ctx.x[10], ctx.x[11], ctx.x[12]]) as usize;
+            let result = syscall(
+                ctx.x[17],
+                [
+                    ctx.x[10], ctx.x[11], ctx.x[12], ctx.x[13], ctx.x[14],
+                    ctx.x[15],
+                ],
+            );
+            // The TrapContext is changed if the syscall is exec.
+            let ctx = current_trap_ctx();
+            ctx.x[10] = result as usize;
         }
-        Trap::Exception(Exception::StoreFault)
-        | Trap::Exception(Exception::StorePageFault) => {
-            println!("[kernel] Store pageFault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, ctx.sepc);
-            exit_current_and_run_next();
+        Trap::Exception(Exception::StoreFault) => {
+            println!("[kernel] Store fault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, ctx.sepc);
+            exit_current_and_run_next(-2);
+        }
+        // The page might be reserved but not populated yet.
+        Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionPageFault) => {
+            let access = match scause.cause() {
+                Trap::Exception(Exception::LoadPageFault) => MapPermission::R,
+                Trap::Exception(Exception::StorePageFault) => MapPermission::W,
+                _ => MapPermission::X,
+            };
+            let result = current_user_memory_set()
+                .exclusive_access()
+                .handle_page_fault(VirtAddr::from(stval).floor(), access);
+            match result {
+                Ok(()) => {}
+                // The fault cannot be reported to the user, hence some task
+                // is killed for the memory, then the access is retried. The
+                // memory set is not borrowed any more.
+                Err(KernelError::OutOfMemory(_)) => oom_kill(),
+                Err(err) => {
+                    println!("[kernel] PageFault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped: {}", stval, ctx.sepc, err);
+                    exit_current_and_run_next(-2);
+                }
+            }
         }
         Trap::Exception(Exception::IllegalInstruction) => {
             println!(
                 "[kernel] IllegalInstruction in application, core dumped."
             );
-            exit_current_and_run_next();
+            exit_current_and_run_next(-3);
         }
-        Trap::Exception(Exception::LoadFault)
-        | Trap::Exception(Exception::LoadPageFault) => {
-            println!("[kernel] Load pageFault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, ctx.sepc);
-            exit_current_and_run_next();
+        Trap::Exception(Exception::LoadFault) => {
+            println!("[kernel] Load fault in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", stval, ctx.sepc);
+            exit_current_and_run_next(-2);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             // Note that we should not have nested interrupt by default; As a
             // result, the interrupts will "stacked" instead. i.e. handling
             // traps one-by-one.
             set_next_trigger();
-            suspend_current_and_run_next();
+            check_timer();
+            if tick_current_task() {
+                suspend_current_and_run_next();
+            }
+        }
+        // Other exceptions from user space, e.g. misaligned access or
+        // breakpoint, only kill the faulty task.
+        Trap::Exception(exception) => {
+            println!("[kernel] Unsupported exception {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.", exception, stval, ctx.sepc);
+            exit_current_and_run_next(-4);
         }
         _ => {
             panic!(
@@ -25,6 +25,9 @@ where
     pub fn get_end(&self) -> T {
         self.end
     }
+    pub fn contains(&self, value: T) -> bool {
+        self.start <= value && value < self.end
+    }
     // Returns a range if there is an intersection between two sets.
     pub fn intersect(&self, another: Self) -> Option<Self> {
         if (another.start <= self.start && self.start < another.end)
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, fork, wait, yield_};
+
+// Spawns the user shell, then keeps reaping the zombie processes which are
+// handed to the init process. Once the shell exits, the init process exits
+// with the same code, which powers off the machine.
+#[no_mangle]
+fn main() -> i32 {
+    let shell_pid = fork();
+    if shell_pid == 0 {
+        exec("user_shell\0");
+        return -1;
+    }
+    loop {
+        let mut exit_code: i32 = 0;
+        let pid = wait(&mut exit_code);
+        if pid == -1 {
+            yield_();
+            continue;
+        }
+        println!(
+            "[initproc] Released a zombie process, pid={}, exit_code={}",
+            pid, exit_code,
+        );
+        if pid == shell_pid {
+            return exit_code;
+        }
+    }
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, waitpid};
+
+/// Expectation:
+/// Test3 cow OK!
+
+const LEN: usize = 4096 * 4;
+static mut DATA: [u8; LEN] = [0; LEN];
+
+#[no_mangle]
+fn main() -> i32 {
+    unsafe {
+        for i in 0..LEN {
+            DATA[i] = i as u8;
+        }
+    }
+    let pid = fork();
+    if pid == 0 {
+        // The child sees the data of the parent and its writes stay private.
+        unsafe {
+            for i in 0..LEN {
+                assert_eq!(DATA[i], i as u8);
+                DATA[i] = !(i as u8);
+            }
+            for i in 0..LEN {
+                assert_eq!(DATA[i], !(i as u8));
+            }
+        }
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    unsafe {
+        for i in 0..LEN {
+            assert_eq!(DATA[i], i as u8);
+        }
+        // Nobody else shares the pages now, the write should work as well.
+        DATA[0] = 42;
+        assert_eq!(DATA[0], 42);
+    }
+    println!("Test3 cow OK!");
+    0
+}
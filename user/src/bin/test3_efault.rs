@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{errno, mmap, munmap, read, task_info, write, TaskInfo, EFAULT};
+
+/// Expectation:
+/// Test3 efault OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096;
+    // Neither the unmapped memory nor the kernel space is accessible.
+    let unmapped =
+        unsafe { core::slice::from_raw_parts(start as *const u8, 16) };
+    assert_eq!(write(1, unmapped), -1);
+    assert_eq!(errno(), EFAULT);
+    // The kernel takes no memory for the size given by the user.
+    let huge =
+        unsafe { core::slice::from_raw_parts(start as *const u8, 1 << 40) };
+    assert_eq!(write(1, huge), -1);
+    assert_eq!(errno(), EFAULT);
+    let kernel = unsafe {
+        core::slice::from_raw_parts(0xffff_ffff_ffff_e000 as *const u8, 16)
+    };
+    assert_eq!(write(1, kernel), -1);
+    assert_eq!(errno(), EFAULT);
+    // The kernel does not write to read-only memory on behalf of the user.
+    assert_eq!(mmap(start, len, 1), 0);
+    let read_only =
+        unsafe { core::slice::from_raw_parts_mut(start as *mut u8, 16) };
+    assert_eq!(read(0, read_only), -1);
+    assert_eq!(errno(), EFAULT);
+    let info = unsafe { &mut *(start as *mut TaskInfo) };
+    assert_eq!(task_info(info), -1);
+    assert_eq!(errno(), EFAULT);
+    assert_eq!(munmap(start, len), 0);
+    println!("Test3 efault OK!");
+    0
+}
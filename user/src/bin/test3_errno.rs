@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    errno, exec, mmap, set_priority, waitpid, write, EBADF, ECHILD, EINVAL,
+    ENOENT,
+};
+
+/// Expectation:
+/// Test3 errno OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    // The kernel reports the failures instead of panicking.
+    assert_eq!(write(42, b"hello"), -1);
+    assert_eq!(errno(), EBADF);
+    assert_eq!(set_priority(1), -1);
+    assert_eq!(errno(), EINVAL);
+    assert_eq!(mmap(0x10000001, 4096, 3), -1);
+    assert_eq!(errno(), EINVAL);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(12345, &mut exit_code), -1);
+    assert_eq!(errno(), ECHILD);
+    assert_eq!(exec("no_such_app\0"), -1);
+    assert_eq!(errno(), ENOENT);
+    println!("Test3 errno OK!");
+    0
+}
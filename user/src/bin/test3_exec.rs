@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exec, exit, fork, waitpid};
+
+/// Expectation:
+/// Test3 forktest OK!
+/// Test3 exec OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(exec("not_an_app\0"), -1);
+        exec("test3_forktest\0");
+        println!("Unreachable after exec, Test3 exec fail!");
+        exit(-1);
+    }
+    let mut exit_code: i32 = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    println!("Test3 exec OK!");
+    0
+}
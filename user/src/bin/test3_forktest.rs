@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, getpid, wait, waitpid};
+
+const MAX_CHILD: usize = 20;
+
+/// Expectation:
+/// Test3 forktest OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    let parent = getpid();
+    let mut pids = [0isize; MAX_CHILD];
+    for i in 0..MAX_CHILD {
+        let pid = fork();
+        if pid == 0 {
+            println!("I am child {}, pid = {}", i, getpid());
+            exit(100 + i as i32);
+        } else {
+            assert!(pid > 0);
+            assert_eq!(getpid(), parent);
+            pids[i] = pid;
+        }
+    }
+    // Reaps the first child by its pid, then the others by any.
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pids[0], &mut exit_code), pids[0]);
+    assert_eq!(exit_code, 100);
+    for _ in 1..MAX_CHILD {
+        let pid = wait(&mut exit_code);
+        assert!(pid > 0);
+        let i = pids.iter().position(|&p| p == pid).unwrap();
+        assert_eq!(exit_code, 100 + i as i32);
+    }
+    assert_eq!(wait(&mut exit_code), -1);
+    println!("Test3 forktest OK!");
+    0
+}
@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use user_lib::{brk, mprotect, sbrk};
+
+/// Expectation:
+/// Test3 heap OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    // Grows and shrinks the heap by sbrk directly.
+    let bottom = sbrk(0);
+    assert!(bottom > 0);
+    assert_eq!(sbrk(4096), bottom);
+    unsafe {
+        *(bottom as *mut usize) = 42;
+        assert_eq!(*(bottom as *const usize), 42);
+    }
+    assert_eq!(sbrk(-4096), bottom + 4096);
+    assert_eq!(brk(0), bottom);
+    // The heap cannot shrink below the bottom.
+    assert_eq!(brk(bottom as usize - 4096), bottom);
+    // Only the pages mapped by mmap can be protected, the heap stays a whole
+    // for sbrk.
+    assert_eq!(sbrk(4096 * 2), bottom);
+    assert_eq!(mprotect(bottom as usize, 4096, 1), -1);
+    assert_eq!(sbrk(4096), bottom + 4096 * 2);
+    unsafe {
+        *((bottom + 4096 * 2) as *mut usize) = 42;
+    }
+    assert_eq!(sbrk(-4096 * 3), bottom + 4096 * 3);
+    assert_eq!(brk(0), bottom);
+    // The allocator grows the heap on demand.
+    let mut v: Vec<usize> = Vec::new();
+    for i in 0..10000 {
+        v.push(i);
+    }
+    for (i, x) in v.iter().enumerate() {
+        assert_eq!(*x, i);
+    }
+    assert!(sbrk(0) > bottom);
+    let mut s = String::new();
+    for _ in 0..100 {
+        s.push_str("hello ");
+    }
+    assert_eq!(s.len(), 600);
+    println!("Test3 heap OK!");
+    0
+}
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, mmap, munmap, waitpid};
+
+/// Expectation:
+/// Test3 lazy_mmap OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    // Far more than the physical memory, which is fine as long as only a few
+    // pages are touched.
+    let start: usize = 0x10000000;
+    let len: usize = 256 * 1024 * 1024;
+    let prot: usize = 3;
+    assert_eq!(0, mmap(start, len, prot));
+    let pages = [start, start + len / 2, start + len - 4096];
+    for &page in pages.iter() {
+        let addr = page as *mut usize;
+        unsafe {
+            assert_eq!(*addr, 0);
+            *addr = page;
+        }
+    }
+    // The child gets a copy of the touched pages only.
+    let pid = fork();
+    if pid == 0 {
+        for &page in pages.iter() {
+            unsafe {
+                assert_eq!(*(page as *const usize), page);
+            }
+        }
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    assert_eq!(0, munmap(start, len));
+    println!("Test3 lazy_mmap OK!");
+    0
+}
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, mmap_with_flags, munmap, waitpid, MAP_ANONYMOUS, MAP_FIXED,
+    MAP_PRIVATE, MAP_SHARED, PROT_READ, PROT_WRITE,
+};
+
+/// Expectation:
+/// Test3 mmap_flags OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    let len: usize = 4096 * 2;
+    let prot = PROT_READ | PROT_WRITE;
+    let private = MAP_PRIVATE | MAP_ANONYMOUS;
+    // Either MAP_SHARED or MAP_PRIVATE, and only the anonymous mapping.
+    assert_eq!(mmap_with_flags(0, len, prot, MAP_ANONYMOUS), -1);
+    assert_eq!(mmap_with_flags(0, len, prot, MAP_PRIVATE), -1);
+    assert_eq!(mmap_with_flags(0, 0, prot, private), -1);
+    // The kernel picks distinct page aligned addresses.
+    let a = mmap_with_flags(0, len, prot, private);
+    let b = mmap_with_flags(0, len, prot, private);
+    assert!(a > 0 && b > 0 && a != b);
+    assert_eq!(a % 4096, 0);
+    assert_eq!(b % 4096, 0);
+    assert!(a + len as isize <= b || b + len as isize <= a);
+    // The hint is taken if the range is free, MAP_FIXED fails otherwise.
+    let hint: usize = 0x10000000;
+    assert_eq!(mmap_with_flags(hint, len, prot, private), hint as isize);
+    let c = mmap_with_flags(hint, len, prot, private);
+    assert!(c > 0 && c != hint as isize);
+    assert_eq!(mmap_with_flags(hint, len, prot, private | MAP_FIXED), -1);
+    // The writes to the shared mapping are visible to the parent.
+    let shared =
+        mmap_with_flags(0, len, prot, MAP_SHARED | MAP_ANONYMOUS) as usize;
+    let private_addr = a as usize;
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            *(shared as *mut usize) = 42;
+            *(private_addr as *mut usize) = 42;
+        }
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    unsafe {
+        assert_eq!(*(shared as *const usize), 42);
+        assert_eq!(*(private_addr as *const usize), 0);
+    }
+    for addr in [a as usize, b as usize, hint, c as usize, shared] {
+        assert_eq!(munmap(addr, len), 0);
+    }
+    println!("Test3 mmap_flags OK!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{fork, mmap, mprotect, munmap, waitpid};
+
+/// Expectation:
+/// Test3 mprotect OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096;
+    assert_eq!(0, mmap(start, len * 3, 3));
+    unsafe {
+        *(start as *mut usize) = 1;
+    }
+    assert_eq!(mprotect(start + 1, len, 1), -1);
+    assert_eq!(mprotect(start, len, 0), -1);
+    assert_eq!(mprotect(start, len, 2), -1);
+    assert_eq!(mprotect(start, 0, 1), -1);
+    assert_eq!(mprotect(start + len * 2, len * 2, 1), -1);
+    // Makes the middle page read-only, then writable again.
+    assert_eq!(mprotect(start + len, len, 1), 0);
+    unsafe {
+        assert_eq!(*((start + len) as *const usize), 0);
+    }
+    assert_eq!(mprotect(start + len, len, 3), 0);
+    unsafe {
+        *((start + len) as *mut usize) = 2;
+    }
+    // Writing to the read-only page kills the child.
+    assert_eq!(mprotect(start, len, 1), 0);
+    let pid = fork();
+    if pid == 0 {
+        unsafe {
+            assert_eq!(*(start as *const usize), 1);
+            *(start as *mut usize) = 3;
+        }
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, -2);
+    assert_eq!(munmap(start, len * 3), 0);
+    println!("Test3 mprotect OK!");
+    0
+}
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{mmap, munmap};
+
+/// Expectation:
+/// Test3 munmap_partial OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    let start: usize = 0x10000000;
+    let len: usize = 4096;
+    let prot: usize = 3;
+    assert_eq!(0, mmap(start, len * 4, prot));
+    for i in 0..4 {
+        unsafe {
+            *((start + i * len) as *mut usize) = i;
+        }
+    }
+    // Punches a hole in the middle, then the page can be mapped again.
+    assert_eq!(munmap(start + len, len), 0);
+    assert_eq!(munmap(start + len, len), -1);
+    assert_eq!(mmap(start + len, len, prot), 0);
+    unsafe {
+        assert_eq!(*((start + len) as *const usize), 0);
+        assert_eq!(*(start as *const usize), 0);
+        assert_eq!(*((start + len * 2) as *const usize), 2);
+        assert_eq!(*((start + len * 3) as *const usize), 3);
+    }
+    // Unmaps across the areas, including the tail of one and the head of the
+    // next.
+    assert_eq!(munmap(start + len * 3, len * 2), -1);
+    assert_eq!(munmap(start, 0), -1);
+    assert_eq!(munmap(start, len * 3), 0);
+    assert_eq!(munmap(start + len * 3, len), 0);
+    assert_eq!(munmap(start, len), -1);
+    println!("Test3 munmap_partial OK!");
+    0
+}
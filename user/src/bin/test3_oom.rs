@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, mmap_with_flags, waitpid, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ,
+    PROT_WRITE,
+};
+
+/// Expectation:
+/// Test3 oom OK!
+
+const PAGE_SIZE: usize = 4096;
+// More than the physical memory and the swap area together by default.
+const LEN: usize = 512 * 1024 * 1024;
+// The exit code of a task killed by the kernel.
+const KILLED_EXIT_CODE: i32 = -9;
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        let start = mmap_with_flags(
+            0,
+            LEN,
+            PROT_READ | PROT_WRITE,
+            MAP_PRIVATE | MAP_ANONYMOUS,
+        );
+        assert!(start > 0);
+        let start = start as usize;
+        // The child is the largest task, which is killed once the memory
+        // runs out.
+        for page in (start..start + LEN).step_by(PAGE_SIZE) {
+            unsafe {
+                *(page as *mut usize) = page;
+            }
+        }
+        println!("The memory never runs out?");
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, KILLED_EXIT_CODE);
+    println!("Test3 oom OK!");
+    0
+}
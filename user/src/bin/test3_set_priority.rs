@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::set_priority;
+
+/// Expectation:
+/// Test3 set_priority OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(set_priority(-5), -1);
+    assert_eq!(set_priority(0), -1);
+    assert_eq!(set_priority(1), -1);
+    assert_eq!(set_priority(2), 2);
+    assert_eq!(set_priority(16), 16);
+    // The largest priority is BIG_STRIDE of the kernel, i.e. 1 << 32.
+    assert_eq!(set_priority(1 << 32), 1 << 32);
+    assert_eq!(set_priority((1 << 32) + 1), -1);
+    assert_eq!(set_priority(isize::MAX), -1);
+    println!("Test3 set_priority OK!");
+    0
+}
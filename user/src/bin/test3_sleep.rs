@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, sleep_ms, task_info, TaskInfo};
+
+/// Expectation:
+/// Test3 sleep OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut before = TaskInfo::new();
+    let mut after = TaskInfo::new();
+    let start = get_time();
+    task_info(&mut before);
+    sleep_ms(500);
+    task_info(&mut after);
+    let end = get_time();
+    assert!(end - start >= 500);
+    // The sleeping task is switched back only once it wakes up, rather than on
+    // every tick.
+    assert!(after.switch_count - before.switch_count <= 2);
+    println!("Test3 sleep OK!");
+    0
+}
@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{exit, fork, get_time, set_priority, waitpid};
+
+const PRIORITIES: [isize; 6] = [5, 6, 7, 8, 9, 10];
+// All children start counting at the same time after they are forked.
+const START_DELAY_MS: isize = 200;
+const DURATION_MS: isize = 2000;
+
+// Counts how many rounds the process runs in [start, end).
+fn count_rounds(start: isize, end: isize) -> i32 {
+    while get_time() < start {}
+    let mut count: i32 = 0;
+    while get_time() < end {
+        count += 1;
+    }
+    count
+}
+
+/// Expectation:
+/// The count of each child is proportional to its priority.
+/// Test3 stride OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    let start = get_time() + START_DELAY_MS;
+    let end = start + DURATION_MS;
+    let mut pids = [0isize; PRIORITIES.len()];
+    for (i, &prio) in PRIORITIES.iter().enumerate() {
+        let pid = fork();
+        if pid == 0 {
+            set_priority(prio);
+            exit(count_rounds(start, end));
+        }
+        pids[i] = pid;
+    }
+    // The count per priority, which is expected to be the same for all.
+    let mut min_ratio = usize::MAX;
+    let mut max_ratio = 0;
+    for (i, &pid) in pids.iter().enumerate() {
+        let mut count: i32 = 0;
+        assert_eq!(waitpid(pid, &mut count), pid);
+        let ratio = count as usize / PRIORITIES[i] as usize;
+        println!(
+            "priority = {}, count = {}, count / priority = {}",
+            PRIORITIES[i], count, ratio
+        );
+        min_ratio = min_ratio.min(ratio);
+        max_ratio = max_ratio.max(ratio);
+    }
+    assert!(min_ratio > 0);
+    // Allows some noise from the timer granularity.
+    assert!(max_ratio * 2 <= min_ratio * 3);
+    println!("Test3 stride OK!");
+    0
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    fork, mmap_with_flags, munmap, waitpid, MAP_ANONYMOUS, MAP_PRIVATE,
+    PROT_READ, PROT_WRITE,
+};
+
+/// Expectation:
+/// Test3 swap OK!
+
+const PAGE_SIZE: usize = 4096;
+// More than the physical memory with `make run TEST=3`, which runs with 32 MiB
+// by default; The pages which do not fit are swapped out.
+const LEN: usize = 40 * 1024 * 1024;
+
+fn check_pages(start: usize, expected: fn(usize) -> usize) {
+    for page in (start..start + LEN).step_by(PAGE_SIZE) {
+        unsafe {
+            assert_eq!(*(page as *const usize), expected(page));
+        }
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let start = mmap_with_flags(
+        0,
+        LEN,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+    );
+    assert!(start > 0);
+    let start = start as usize;
+    for page in (start..start + LEN).step_by(PAGE_SIZE) {
+        unsafe {
+            *(page as *mut usize) = page;
+        }
+    }
+    // The pages written first are swapped in again.
+    check_pages(start, |page| page);
+    // The child gets a copy of both the pages in memory and the swapped out
+    // ones.
+    let pid = fork();
+    if pid == 0 {
+        check_pages(start, |page| page);
+        for page in (start..start + LEN).step_by(PAGE_SIZE) {
+            unsafe {
+                *(page as *mut usize) = !page;
+            }
+        }
+        check_pages(start, |page| !page);
+        return 0;
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    check_pages(start, |page| page);
+    assert_eq!(0, munmap(start, LEN));
+    println!("Test3 swap OK!");
+    0
+}
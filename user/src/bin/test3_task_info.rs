@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{get_time, task_info, yield_, TaskInfo, TaskStatus};
+
+const SYSCALL_YIELD: usize = 124;
+const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_TASK_INFO: usize = 410;
+
+/// Expectation:
+/// Test3 task_info OK!
+
+#[no_mangle]
+fn main() -> i32 {
+    let t1 = get_time();
+    for _ in 0..10 {
+        yield_();
+    }
+    let mut info = TaskInfo::new();
+    assert_eq!(task_info(&mut info), 0);
+    let t2 = get_time();
+    assert_eq!(info.status, TaskStatus::Running);
+    assert_eq!(info.syscall_times[SYSCALL_YIELD], 10);
+    assert_eq!(info.syscall_times[SYSCALL_GET_TIME], 1);
+    assert_eq!(info.syscall_times[SYSCALL_TASK_INFO], 1);
+    assert!(t2 - t1 <= info.time as isize + 1);
+    // Each yield switches the task out and back in.
+    assert!(info.switch_count > 10);
+    assert!(info.kernel_time > 0);
+    println!("Test3 task_info OK!");
+    0
+}
@@ -0,0 +1,77 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::console::getchar;
+use user_lib::{exec, fork, waitpid};
+
+const LF: u8 = 0x0a;
+const CR: u8 = 0x0d;
+const DL: u8 = 0x7f;
+const BS: u8 = 0x08;
+// The max length of a line including the trailing '\0'.
+const LINE_MAX: usize = 128;
+
+// Exits the shell, the exit code is non-zero if any process exited with a
+// non-zero code.
+const EXIT: &[u8] = b"exit";
+
+#[no_mangle]
+fn main() -> i32 {
+    println!("Rust user shell");
+    let mut line = [0u8; LINE_MAX];
+    let mut len: usize = 0;
+    let mut failed = false;
+    print!(">> ");
+    loop {
+        let c = getchar();
+        match c {
+            LF | CR => {
+                println!("");
+                if &line[..len] == EXIT {
+                    return failed as i32;
+                }
+                if len > 0 {
+                    line[len] = b'\0';
+                    // The app name must end with '\0' for exec.
+                    let path = core::str::from_utf8(&line[..=len]).unwrap();
+                    let pid = fork();
+                    if pid == 0 {
+                        if exec(path) == -1 {
+                            println!("Error when executing!");
+                            return -4;
+                        }
+                        unreachable!();
+                    } else {
+                        let mut exit_code: i32 = 0;
+                        let exit_pid = waitpid(pid, &mut exit_code);
+                        assert_eq!(pid, exit_pid);
+                        failed |= exit_code != 0;
+                        println!(
+                            "Shell: Process {} exited with code {}",
+                            pid, exit_code
+                        );
+                    }
+                    len = 0;
+                }
+                print!(">> ");
+            }
+            BS | DL => {
+                if len > 0 {
+                    // Erases the last char on the console.
+                    print!("{} {}", BS as char, BS as char);
+                    len -= 1;
+                }
+            }
+            _ => {
+                if c.is_ascii() && len + 1 < LINE_MAX {
+                    print!("{}", c as char);
+                    line[len] = c;
+                    len += 1;
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use core::fmt::{self, Write};
 
+const STDIN: usize = 0;
 const STDOUT: usize = 1;
 
-use super::write;
+use super::{read, write};
 
 struct Stdout;
 
@@ -13,6 +14,12 @@ impl Write for Stdout {
     }
 }
 
+pub fn getchar() -> u8 {
+    let mut c = [0u8; 1];
+    read(STDIN, &mut c);
+    c[0]
+}
+
 pub fn print(args: fmt::Arguments) {
     Stdout.write_fmt(args).unwrap();
 }
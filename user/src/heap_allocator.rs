@@ -0,0 +1,48 @@
+// The heap of user apps, which starts empty and grows by sbrk on demand.
+use crate::sbrk;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{null_mut, NonNull};
+use linked_list_allocator::LockedHeap;
+
+// The heap grows by at least this size each time to save syscalls.
+const HEAP_GROW_SIZE: usize = 4096 * 4;
+
+struct BrkHeap(LockedHeap);
+
+#[global_allocator]
+static HEAP_ALLOCATOR: BrkHeap = BrkHeap(LockedHeap::empty());
+
+unsafe impl GlobalAlloc for BrkHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut heap = self.0.lock();
+        loop {
+            if let Ok(ptr) = heap.allocate_first_fit(layout) {
+                return ptr.as_ptr();
+            }
+            // The extra alignment makes sure the layout fits in the grown
+            // memory whatever the padding is.
+            let size = (layout.size() + layout.align()).max(HEAP_GROW_SIZE);
+            let size = (size + 4095) & !4095;
+            let old_brk = sbrk(size as isize);
+            if old_brk == -1 {
+                return null_mut();
+            }
+            if heap.size() == 0 {
+                heap.init(old_brk as *mut u8, size);
+            } else {
+                heap.extend(size);
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0
+            .lock()
+            .deallocate(NonNull::new_unchecked(ptr), layout);
+    }
+}
+
+#[alloc_error_handler]
+pub fn handle_alloc_error(layout: Layout) -> ! {
+    panic!("Heap allocation error, layout = {:?}", layout);
+}
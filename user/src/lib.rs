@@ -1,12 +1,16 @@
 #![no_std]
 #![feature(linkage)]
 #![feature(panic_info_message)]
+#![feature(alloc_error_handler)]
 
 #[macro_use]
 pub mod console;
+mod heap_allocator;
 mod lang_items;
 mod syscall;
 
+extern crate alloc;
+
 // The "real" entry point for each user binary.
 #[no_mangle]
 #[link_section = ".text.entry"]
@@ -23,16 +27,54 @@ fn main() -> i32 {
     panic!("Cannot find main!");
 }
 
+use core::sync::atomic::{AtomicIsize, Ordering};
 use syscall::*;
 
+// The error numbers set by the failed syscalls, same as the kernel.
+pub const ENOENT: isize = 2;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EAGAIN: isize = 11;
+pub const ENOMEM: isize = 12;
+pub const EFAULT: isize = 14;
+pub const EINVAL: isize = 22;
+pub const ENOSYS: isize = 38;
+
+static ERRNO: AtomicIsize = AtomicIsize::new(0);
+
+// Return the error number of the last failed syscall.
+pub fn errno() -> isize {
+    ERRNO.load(Ordering::Relaxed)
+}
+
+// The kernel returns -errno on failure, which is turned into -1 with the
+// errno kept aside like libc does.
+fn check(ret: isize) -> isize {
+    if ret < 0 {
+        ERRNO.store(-ret, Ordering::Relaxed);
+        return -1;
+    }
+    ret
+}
+
+// Reading stdin waits until at least one byte arrives, then returns the
+// number of bytes filled into the buffer.
+pub fn read(fd: usize, buf: &mut [u8]) -> isize {
+    check(sys_read(fd, buf))
+}
 pub fn write(fd: usize, buf: &[u8]) -> isize {
-    sys_write(fd, buf)
+    check(sys_write(fd, buf))
 }
 pub fn exit(exit_code: i32) -> isize {
     sys_exit(exit_code)
 }
 pub fn yield_() -> isize {
-    sys_yield()
+    check(sys_yield())
+}
+// The priority must be at least 2, the CPU share of a process is proportional
+// to its priority.
+pub fn set_priority(prio: isize) -> isize {
+    check(sys_set_priority(prio))
 }
 
 #[repr(C)]
@@ -54,25 +96,136 @@ pub fn get_time() -> isize {
         _ => -1,
     }
 }
+// The task is parked in the kernel and not scheduled until the time passes.
 pub fn sleep_ms(time: isize) -> isize {
-    let ddl = get_time() + time;
-    loop {
-        let now = get_time();
-        if now > ddl {
-            break;
-        }
-        let ret = yield_();
-        if ret != 0 {
-            return ret;
-        }
+    check(sys_sleep(time.max(0) as usize))
+}
+
+// Sets the program break, i.e. the end of the heap, or queries it if the addr
+// is 0. Return the program break after the change.
+pub fn brk(addr: usize) -> isize {
+    sys_brk(addr)
+}
+// Grows or shrinks the heap by the given size, return the old program break
+// if success and -1 if fail.
+pub fn sbrk(size: isize) -> isize {
+    let old_brk = sys_brk(0);
+    if size == 0 {
+        return old_brk;
+    }
+    let new_brk = old_brk + size;
+    if new_brk < 0 || sys_brk(new_brk as usize) != new_brk {
+        return -1;
     }
-    0
+    old_brk
 }
 
+pub const PROT_READ: usize = 1 << 0;
+pub const PROT_WRITE: usize = 1 << 1;
+pub const PROT_EXEC: usize = 1 << 2;
+pub const MAP_SHARED: usize = 1 << 0;
+pub const MAP_PRIVATE: usize = 1 << 1;
+pub const MAP_FIXED: usize = 1 << 4;
+pub const MAP_ANONYMOUS: usize = 1 << 5;
+
+// Maps anonymous private memory at exactly the given start, return 0 if
+// success and -1 if fail.
 pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
-    sys_mmap(start, len, prot)
+    match mmap_with_flags(
+        start,
+        len,
+        prot,
+        MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED,
+    ) {
+        -1 => -1,
+        _ => 0,
+    }
+}
+// Maps anonymous memory with the Linux-style flags, the address is picked by
+// the kernel unless MAP_FIXED is given. Return the start of the mapped memory
+// if success and -1 if fail.
+pub fn mmap_with_flags(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+) -> isize {
+    check(sys_mmap(addr, len, prot, flags, usize::MAX, 0))
 }
 
 pub fn munmap(start: usize, len: usize) -> isize {
-    sys_munmap(start, len)
+    check(sys_munmap(start, len))
+}
+
+pub fn mprotect(start: usize, len: usize, prot: usize) -> isize {
+    check(sys_mprotect(start, len, prot))
+}
+
+pub fn getpid() -> isize {
+    check(sys_getpid())
+}
+pub fn fork() -> isize {
+    check(sys_fork())
+}
+// The path is the name of the app and it must end with '\0'.
+pub fn exec(path: &str) -> isize {
+    check(sys_exec(path))
+}
+// Waits for any child to exit, yielding while none has exited yet.
+pub fn wait(exit_code: &mut i32) -> isize {
+    waitpid(-1, exit_code)
+}
+// Waits for the given child to exit, yielding while it has not exited yet.
+pub fn waitpid(pid: isize, exit_code: &mut i32) -> isize {
+    loop {
+        match sys_waitpid(pid, exit_code as *mut _) {
+            ret if ret == -EAGAIN => {
+                yield_();
+            }
+            ret => return check(ret),
+        }
+    }
+}
+
+pub const MAX_SYSCALL_NUM: usize = 500;
+
+// Mirrors the TaskStatus in the kernel.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    Ready,
+    Running,
+    Blocked,
+    Zombie,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct TaskInfo {
+    pub status: TaskStatus,
+    pub syscall_times: [u32; MAX_SYSCALL_NUM],
+    // Milliseconds since the task was scheduled for the first time.
+    pub time: usize,
+    // Microseconds spent in user space and kernel space respectively.
+    pub user_time: usize,
+    pub kernel_time: usize,
+    pub switch_count: usize,
+}
+
+impl TaskInfo {
+    pub fn new() -> Self {
+        Self {
+            status: TaskStatus::Ready,
+            syscall_times: [0; MAX_SYSCALL_NUM],
+            time: 0,
+            user_time: 0,
+            kernel_time: 0,
+            switch_count: 0,
+        }
+    }
+}
+// Queries the statistics of the current process, the syscall counts include
+// this call itself.
+pub fn task_info(info: &mut TaskInfo) -> isize {
+    check(sys_task_info(info))
 }